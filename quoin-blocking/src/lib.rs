@@ -0,0 +1,125 @@
+use quoin_device::{AsyncDevice, Device};
+
+use std::sync::{Arc, Mutex};
+
+use async_trait::async_trait;
+
+/// Exposes a [Device] as an [AsyncDevice]
+///
+/// Each operation runs on the blocking thread pool of the current tokio
+/// runtime, so this must be used from within one.
+pub struct Blocking<T: Device<SIZE>, const SIZE: usize> {
+    device: Arc<Mutex<T>>,
+    len: u64,
+}
+
+impl<T: Device<SIZE> + Send + 'static, const SIZE: usize> Blocking<T, SIZE> {
+    pub fn new(device: T) -> Self {
+        Self {
+            len: device.len(),
+            device: Arc::new(Mutex::new(device)),
+        }
+    }
+
+    async fn run<R, F>(&self, func: F) -> R
+    where
+        R: Send + 'static,
+        F: FnOnce(&mut T) -> R + Send + 'static,
+    {
+        let device = self.device.clone();
+        let task = tokio::task::spawn_blocking(move || func(&mut device.lock().unwrap()));
+
+        match task.await {
+            Ok(result) => result,
+            Err(e) => std::panic::resume_unwind(e.into_panic()),
+        }
+    }
+}
+
+#[async_trait]
+impl<T: Device<SIZE> + Send + 'static, const SIZE: usize> AsyncDevice<SIZE> for Blocking<T, SIZE> {
+    type Error = T::Error;
+
+    #[inline]
+    fn len(&self) -> u64 {
+        self.len
+    }
+
+    async fn read(
+        &mut self,
+        index: u64,
+        mut block: Box<[u8; SIZE]>,
+    ) -> Result<Box<[u8; SIZE]>, Self::Error> {
+        self.run(move |device| {
+            *block = device.get(index)?;
+            Ok(block)
+        })
+        .await
+    }
+
+    async fn write(
+        &mut self,
+        index: u64,
+        block: Box<[u8; SIZE]>,
+    ) -> Result<Box<[u8; SIZE]>, Self::Error> {
+        self.run(move |device| {
+            device.set(index, &block)?;
+            Ok(block)
+        })
+        .await
+    }
+
+    async fn flush(&mut self) -> Result<(), Self::Error> {
+        self.run(|device| device.flush()).await
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    use quoin_memory::Memory;
+
+    use std::io::{Error, ErrorKind};
+
+    // Fails to write the last block
+    struct ReadOnlyTail(Memory<512, 4>);
+
+    impl Device<512> for ReadOnlyTail {
+        type Error = Error;
+
+        fn len(&self) -> u64 {
+            self.0.len()
+        }
+
+        fn get(&mut self, index: u64) -> Result<[u8; 512], Self::Error> {
+            Ok(self.0.get(index).unwrap())
+        }
+
+        fn set(&mut self, index: u64, block: &[u8; 512]) -> Result<(), Self::Error> {
+            if index == 3 {
+                return Err(ErrorKind::PermissionDenied.into());
+            }
+
+            self.0.set(index, block).unwrap();
+            Ok(())
+        }
+    }
+
+    #[tokio::test]
+    async fn blocking() {
+        let mut device = Blocking::new(ReadOnlyTail(Memory::default()));
+        assert_eq!(device.len(), 4);
+
+        let block = device.write(2, Box::new([7; 512])).await.unwrap();
+        assert_eq!(*block, [7; 512]);
+        device.flush().await.unwrap();
+
+        let block = device.read(2, Box::new([0; 512])).await.unwrap();
+        assert_eq!(*block, [7; 512]);
+
+        // Errors from the device are returned as they are.
+        let err = device.write(3, block).await.unwrap_err();
+        assert_eq!(err.kind(), ErrorKind::PermissionDenied);
+    }
+}
@@ -0,0 +1,402 @@
+use std::io::Write;
+use std::path::{Path, PathBuf};
+
+use anyhow::{anyhow, bail, Context, Result};
+use quoin_device::{Device, DynDevice};
+use quoin_file::{File, OpenOptions};
+use quoin_gpt::{Alignment, Disk, Kind, Layout, Options, Strategy};
+use quoin_stack::{Config, Stack};
+use structopt::StructOpt;
+use uuid::Uuid;
+
+/// Manages GPT partition tables on disk images and block devices
+#[derive(StructOpt)]
+#[structopt(name = "quoin")]
+struct Args {
+    /// The block size of the device (512 or 4096)
+    #[structopt(short, long, default_value = "512")]
+    block_size: usize,
+
+    #[structopt(subcommand)]
+    command: Command,
+}
+
+#[derive(StructOpt)]
+enum Command {
+    /// Prints the partition table
+    Print { device: PathBuf },
+
+    /// Writes a new, empty partition table
+    Create {
+        device: PathBuf,
+
+        /// The disk GUID
+        #[structopt(long)]
+        guid: Option<Uuid>,
+
+        /// The number of entry slots
+        #[structopt(long)]
+        slots: Option<u32>,
+
+        /// The first usable block
+        #[structopt(long)]
+        first: Option<u64>,
+    },
+
+    /// Adds a partition in the first hole that fits it
+    Add {
+        device: PathBuf,
+
+        /// The size of the partition (e.g. 512M)
+        #[structopt(parse(try_from_str = parse_size))]
+        size: u64,
+
+        /// The partition type, by name or GUID
+        #[structopt(short = "t", long = "type", default_value = "Linux filesystem")]
+        kind: String,
+
+        /// The partition name
+        #[structopt(short, long, default_value = "")]
+        name: String,
+
+        /// The partition number (the first free one by default)
+        #[structopt(long)]
+        number: Option<u32>,
+
+        /// The alignment of the partition start (1M by default)
+        #[structopt(long, parse(try_from_str = parse_align))]
+        align: Option<u64>,
+    },
+
+    /// Removes a partition
+    Remove { device: PathBuf, number: u32 },
+
+    /// Changes the size of a partition without moving its start
+    Resize {
+        device: PathBuf,
+        number: u32,
+
+        /// The new size of the partition (e.g. 1G)
+        #[structopt(parse(try_from_str = parse_size))]
+        size: u64,
+    },
+
+    /// Checks both copies of the partition table for problems
+    Verify { device: PathBuf },
+
+    /// Saves the partition table as an sfdisk script
+    Backup { device: PathBuf, file: PathBuf },
+
+    /// Writes the partition table from an sfdisk script
+    Restore { device: PathBuf, file: PathBuf },
+
+    /// Creates a sparse disk image, or grows or truncates an existing one
+    Image {
+        path: PathBuf,
+
+        /// The size of the image (e.g. 8G)
+        #[structopt(parse(try_from_str = parse_size))]
+        size: u64,
+    },
+
+    /// Opens a device stack (TOML or JSON) and describes its layers
+    Inspect { config: PathBuf },
+
+    /// Opens a device stack and writes one of its blocks to stdout
+    Read { config: PathBuf, index: u64 },
+}
+
+// Parses a size in bytes with an optional binary unit (e.g. 4K or 1MiB).
+fn parse_size(size: &str) -> Result<u64> {
+    let split = size
+        .find(|c: char| !c.is_ascii_digit())
+        .unwrap_or(size.len());
+    let (number, unit) = size.split_at(split);
+
+    let shift = match unit.trim() {
+        "" | "B" => 0,
+        "K" | "KiB" => 10,
+        "M" | "MiB" => 20,
+        "G" | "GiB" => 30,
+        "T" | "TiB" => 40,
+        _ => bail!("invalid unit: {}", unit),
+    };
+
+    let number: u64 = number.parse().context("invalid size")?;
+    number
+        .checked_mul(1 << shift)
+        .ok_or_else(|| anyhow!("size is too large"))
+}
+
+fn parse_align(align: &str) -> Result<u64> {
+    match parse_size(align)? {
+        0 => bail!("the alignment must not be zero"),
+        align => Ok(align),
+    }
+}
+
+fn parse_kind(kind: &str) -> Result<Uuid> {
+    match Kind::from_name(kind) {
+        Some(kind) => Ok(kind.guid),
+        None => kind.parse().context("unknown partition type"),
+    }
+}
+
+fn human(bytes: u64) -> String {
+    const UNITS: &[&str] = &["B", "KiB", "MiB", "GiB", "TiB", "PiB"];
+
+    let mut size = bytes as f64;
+    let mut unit = 0;
+    while size >= 1024.0 && unit < UNITS.len() - 1 {
+        size /= 1024.0;
+        unit += 1;
+    }
+
+    format!("{:.1} {}", size, UNITS[unit])
+}
+
+fn open<const SIZE: usize>(path: &Path, read_only: bool) -> Result<File<SIZE>> {
+    OpenOptions::new()
+        .read_only(read_only)
+        .open(path)
+        .with_context(|| format!("unable to open {}", path.display()))
+}
+
+fn stack(path: &Path, read_only: bool) -> Result<Stack> {
+    let config = std::fs::read_to_string(path)
+        .with_context(|| format!("unable to read {}", path.display()))?;
+
+    let config = match path.extension() {
+        Some(ext) if ext == "json" => Config::from_json(&config)?,
+        _ => Config::from_toml(&config)?,
+    };
+
+    match read_only {
+        true => Ok(Stack::open_read_only(&config)?),
+        false => Ok(Stack::open(&config)?),
+    }
+}
+
+fn load<const SIZE: usize>(path: &Path, read_only: bool) -> Result<Disk<File<SIZE>, SIZE>> {
+    Disk::load(open(path, read_only)?)?
+        .ok_or_else(|| anyhow!("{}: no partition table found", path.display()))
+}
+
+fn run<const SIZE: usize>(command: Command) -> Result<()> {
+    match command {
+        Command::Print { device } => {
+            let disk = load::<SIZE>(&device, true)?;
+            let layout = disk.layout();
+
+            println!("Disk: {}", device.display());
+            println!("GUID: {}", disk.guid());
+            println!("Block size: {}", SIZE);
+            println!(
+                "Usable blocks: {}..={}",
+                layout.first.unwrap_or_default(),
+                layout.last.unwrap_or_default()
+            );
+            println!();
+
+            println!(
+                "{:>6} {:>12} {:>12} {:>10}  {:<24} Name",
+                "Number", "Start", "End", "Size", "Type"
+            );
+
+            for part in disk.partitions() {
+                let kind = match Kind::from_guid(part.kind()) {
+                    Some(kind) => kind.name.to_string(),
+                    None => part.kind().to_string(),
+                };
+
+                println!(
+                    "{:>6} {:>12} {:>12} {:>10}  {:<24} {}",
+                    part.number(),
+                    part.blocks().start,
+                    part.blocks().end - 1,
+                    human(part.len() * SIZE as u64),
+                    kind,
+                    part.name().unwrap_or_default()
+                );
+            }
+        }
+
+        Command::Create {
+            device,
+            guid,
+            slots,
+            first,
+        } => {
+            let options = Options {
+                guid,
+                slots,
+                first,
+                last: None,
+            };
+
+            let disk = Disk::format_with(open::<SIZE>(&device, false)?, options)?;
+            println!("Created partition table {}", disk.guid());
+        }
+
+        Command::Add {
+            device,
+            size,
+            kind,
+            name,
+            number,
+            align,
+        } => {
+            let kind = parse_kind(&kind)?;
+            let file = open::<SIZE>(&device, false)?;
+
+            // Keep partitions aligned to the device's physical blocks.
+            let geometry = *file.geometry();
+            let alignment = Alignment {
+                bytes: align,
+                physical_block_size: Some(geometry.physical_block_size.into()),
+                alignment_offset: Some(geometry.alignment_offset.into()),
+            };
+
+            let mut disk = Disk::load(file)?
+                .ok_or_else(|| anyhow!("{}: no partition table found", device.display()))?;
+            let blocks = disk
+                .allocate(size, alignment, Strategy::FirstFit)
+                .ok_or_else(|| anyhow!("no room for a partition of {}", human(size)))?;
+
+            let attr = Default::default();
+            let number = match number {
+                Some(number) => disk
+                    .add_at(number, kind, blocks, attr, &name)
+                    .map(|_| number),
+                None => disk.add(kind, blocks, attr, &name),
+            };
+
+            println!("Added partition {}", number?);
+        }
+
+        Command::Remove { device, number } => {
+            load::<SIZE>(&device, false)?.remove(number)?;
+        }
+
+        Command::Resize {
+            device,
+            number,
+            size,
+        } => {
+            let blocks = size.div_ceil(SIZE as u64);
+            load::<SIZE>(&device, false)?.resize(number, blocks)?;
+        }
+
+        Command::Verify { device } => {
+            let findings = Disk::check(open::<SIZE>(&device, true)?)?;
+            for finding in &findings {
+                println!("{:?}", finding);
+            }
+
+            if !findings.is_empty() {
+                bail!("{}: found {} problem(s)", device.display(), findings.len());
+            }
+
+            println!("{}: no problems found", device.display());
+        }
+
+        Command::Backup { device, file } => {
+            let mut layout = load::<SIZE>(&device, true)?.layout();
+            layout.device = Some(device.display().to_string());
+
+            std::fs::write(&file, layout.to_string())
+                .with_context(|| format!("unable to write {}", file.display()))?;
+        }
+
+        Command::Restore { device, file } => {
+            let script = std::fs::read_to_string(&file)
+                .with_context(|| format!("unable to read {}", file.display()))?;
+            let layout: Layout = script
+                .parse()
+                .with_context(|| format!("invalid script {}", file.display()))?;
+
+            let disk = Disk::apply(open::<SIZE>(&device, false)?, &layout)?;
+            println!("Restored partition table {}", disk.guid());
+        }
+
+        Command::Image { path, size } => {
+            let blocks = size.div_ceil(SIZE as u64);
+            let file = match path.exists() {
+                false => File::<SIZE>::create(&path, blocks)
+                    .with_context(|| format!("unable to create {}", path.display()))?,
+                true => {
+                    let mut file = open::<SIZE>(&path, false)?;
+                    file.resize(blocks)
+                        .with_context(|| format!("unable to resize {}", path.display()))?;
+                    file
+                }
+            };
+
+            println!(
+                "{}: {} ({} allocated)",
+                path.display(),
+                human(file.len() * SIZE as u64),
+                human(file.allocated()?)
+            );
+        }
+
+        Command::Inspect { config } => {
+            println!("{:<10} {:>10} {:>12}", "Layer", "Block size", "Blocks");
+            for level in stack(&config, true)?.levels() {
+                println!(
+                    "{:<10} {:>10} {:>12}",
+                    level.name, level.block_size, level.len
+                );
+            }
+        }
+
+        Command::Read { config, index } => {
+            let mut stack = stack(&config, true)?;
+            let mut block = vec![0u8; stack.block_size()];
+            stack.get(index, &mut block)?;
+            std::io::stdout().write_all(&block)?;
+        }
+    }
+
+    Ok(())
+}
+
+fn main() -> Result<()> {
+    let args = Args::from_args();
+
+    match args.block_size {
+        512 => run::<512>(args.command),
+        4096 => run::<4096>(args.command),
+        size => bail!("unsupported block size: {}", size),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn size() {
+        assert_eq!(parse_size("512").unwrap(), 512);
+        assert_eq!(parse_size("4K").unwrap(), 4096);
+        assert_eq!(parse_size("1MiB").unwrap(), 1 << 20);
+        assert_eq!(parse_size("2G").unwrap(), 2 << 30);
+        assert!(parse_size("1X").is_err());
+        assert!(parse_size("M").is_err());
+        assert!(parse_size("99999999T").is_err());
+
+        assert_eq!(parse_align("1M").unwrap(), 1 << 20);
+        assert!(parse_align("0").is_err());
+        assert!(parse_align("0K").is_err());
+    }
+
+    #[test]
+    fn kind() {
+        assert_eq!(parse_kind("linux swap").unwrap(), Kind::LINUX_SWAP.guid);
+        assert_eq!(
+            parse_kind("0657fd6d-a4ab-43c4-84e5-0933c84b4f4f").unwrap(),
+            Kind::LINUX_SWAP.guid
+        );
+        assert!(parse_kind("nonsense").is_err());
+    }
+}
@@ -47,6 +47,11 @@ impl<T: Device<SIZE>, const SIZE: usize> Device<SIZE> for Corrupt<T, SIZE> {
     fn set(&mut self, index: u64, block: &[u8; SIZE]) -> Result<(), Self::Error> {
         self.device.set(index, block)
     }
+
+    #[inline]
+    fn flush(&mut self) -> Result<(), Self::Error> {
+        self.device.flush()
+    }
 }
 
 #[cfg(test)]
@@ -0,0 +1,114 @@
+//! Asynchronous block encryption
+
+use super::{apply, check, Error};
+
+use quoin_device::AsyncDevice;
+
+use async_trait::async_trait;
+use openssl::symm::{Cipher, Mode};
+
+/// Encrypts each block of an [AsyncDevice]
+///
+/// The blocks are compatible with those of [super::Crypt].
+pub struct Crypt<T: AsyncDevice<SIZE>, K: AsRef<[u8]>, const SIZE: usize> {
+    device: T,
+    secret: K,
+    cipher: Cipher,
+}
+
+impl<T: AsyncDevice<SIZE>, K: AsRef<[u8]>, const SIZE: usize> Crypt<T, K, SIZE> {
+    #[inline]
+    pub fn new(device: T, secret: K, cipher: Cipher) -> Result<Self, Error<T::Error>> {
+        check::<SIZE>(cipher, secret.as_ref());
+
+        Ok(Self {
+            device,
+            cipher,
+            secret,
+        })
+    }
+}
+
+#[async_trait]
+impl<T: AsyncDevice<SIZE>, K: AsRef<[u8]> + Send, const SIZE: usize> AsyncDevice<SIZE>
+    for Crypt<T, K, SIZE>
+{
+    type Error = Error<T::Error>;
+
+    #[inline]
+    fn len(&self) -> u64 {
+        self.device.len()
+    }
+
+    async fn read(
+        &mut self,
+        index: u64,
+        block: Box<[u8; SIZE]>,
+    ) -> Result<Box<[u8; SIZE]>, Self::Error> {
+        let mut block = self
+            .device
+            .read(index, block)
+            .await
+            .map_err(Error::Parent)?;
+
+        let secret = self.secret.as_ref();
+        *block = apply(self.cipher, Mode::Decrypt, secret, index, &block)?;
+        Ok(block)
+    }
+
+    async fn write(
+        &mut self,
+        index: u64,
+        block: Box<[u8; SIZE]>,
+    ) -> Result<Box<[u8; SIZE]>, Self::Error> {
+        let secret = self.secret.as_ref();
+        let ciphertext = apply(self.cipher, Mode::Encrypt, secret, index, &block)?;
+
+        let ciphertext = Box::new(ciphertext);
+        self.device
+            .write(index, ciphertext)
+            .await
+            .map_err(Error::Parent)?;
+        Ok(block)
+    }
+
+    #[inline]
+    async fn flush(&mut self) -> Result<(), Self::Error> {
+        self.device.flush().await.map_err(Error::Parent)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    use quoin_blocking::Blocking;
+    use quoin_device::Device;
+    use quoin_memory::Memory;
+    use rand::Rng;
+
+    #[tokio::test]
+    async fn crypt() {
+        const ZERO: [u8; 512] = [0; 512];
+
+        let mut key = [0u8; 64];
+        rand::thread_rng().fill(&mut key);
+        let mut device = Blocking::new(Memory::<512, 1>::default());
+
+        let mut crypt = Crypt::new(&mut device, key, Cipher::aes_256_xts()).unwrap();
+        crypt.write(0, Box::new(ZERO)).await.unwrap();
+
+        let block = device.read(0, Box::new(ZERO)).await.unwrap();
+        assert_ne!(*block, ZERO);
+
+        // The ciphertext matches that of the synchronous implementation.
+        let mut memory: Memory<512, 1> = Memory::default();
+        memory.set(0, &block).unwrap();
+        let mut sync = crate::Crypt::new(memory, key, Cipher::aes_256_xts()).unwrap();
+        assert_eq!(sync.get(0).unwrap(), ZERO);
+
+        let mut crypt = Crypt::new(&mut device, key, Cipher::aes_256_xts()).unwrap();
+        let block = crypt.read(0, block).await.unwrap();
+        assert_eq!(*block, ZERO);
+    }
+}
@@ -1,13 +1,21 @@
+#[cfg(feature = "async")]
+pub mod aio;
+
 use core::mem::size_of;
 
-use quoin_device::Device;
+use quoin_device::{Device, ErrorKind};
+
+use std::fmt::{Display, Formatter};
 
 use openssl::error::ErrorStack;
 use openssl::symm::{Cipher, Crypter, Mode};
 
 #[derive(Clone, Debug)]
 pub enum Error<T> {
+    /// The underlying device failed
     Parent(T),
+
+    /// The block could not be encrypted or decrypted
     Crypto(ErrorStack),
 }
 
@@ -18,6 +26,59 @@ impl<T> From<ErrorStack> for Error<T> {
     }
 }
 
+impl<T> Display for Error<T> {
+    fn fmt(&self, f: &mut Formatter) -> std::fmt::Result {
+        f.write_str(match self {
+            Self::Parent(..) => "device error",
+            Self::Crypto(..) => "unable to encrypt or decrypt the block",
+        })
+    }
+}
+
+impl<T: std::error::Error + 'static> std::error::Error for Error<T> {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Self::Parent(e) => Some(e),
+            Self::Crypto(e) => Some(e),
+        }
+    }
+}
+
+impl<T: quoin_device::Error> quoin_device::Error for Error<T> {
+    fn kind(&self) -> ErrorKind {
+        match self {
+            Self::Parent(e) => e.kind(),
+            Self::Crypto(..) => ErrorKind::Other,
+        }
+    }
+}
+
+// Encrypts or decrypts one block, using its index as the tweak.
+fn apply<const SIZE: usize>(
+    cipher: Cipher,
+    mode: Mode,
+    secret: &[u8],
+    index: u64,
+    input: &[u8; SIZE],
+) -> Result<[u8; SIZE], ErrorStack> {
+    let iv = u128::from(index).to_le_bytes();
+    let mut crypter = Crypter::new(cipher, mode, secret, Some(&iv))?;
+
+    let mut output = [0u8; SIZE];
+    let update = crypter.update(input, &mut output)?;
+    let finalize = crypter.finalize(&mut output[update..])?;
+    assert_eq!(update + finalize, SIZE);
+
+    Ok(output)
+}
+
+// Checks that the cipher can be used as a block device cipher.
+fn check<const SIZE: usize>(cipher: Cipher, secret: &[u8]) {
+    assert_eq!(cipher.iv_len(), Some(size_of::<u128>()));
+    assert_eq!(cipher.key_len(), secret.len());
+    assert_eq!(SIZE % cipher.block_size(), 0);
+}
+
 pub struct Crypt<T: Device<SIZE>, K: AsRef<[u8]>, const SIZE: usize> {
     device: T,
     secret: K,
@@ -27,9 +88,7 @@ pub struct Crypt<T: Device<SIZE>, K: AsRef<[u8]>, const SIZE: usize> {
 impl<T: Device<SIZE>, K: AsRef<[u8]>, const SIZE: usize> Crypt<T, K, SIZE> {
     #[inline]
     pub fn new(device: T, secret: K, cipher: Cipher) -> Result<Self, Error<T::Error>> {
-        assert_eq!(cipher.iv_len(), Some(size_of::<u128>()));
-        assert_eq!(cipher.key_len(), secret.as_ref().len());
-        assert_eq!(SIZE % cipher.block_size(), 0);
+        check::<SIZE>(cipher, secret.as_ref());
 
         Ok(Self {
             device,
@@ -54,39 +113,27 @@ impl<T: Device<SIZE>, K: AsRef<[u8]>, const SIZE: usize> Device<SIZE> for Crypt<
             Err(e) => return Err(Error::Parent(e)),
         };
 
-        let mut crypter = Crypter::new(
+        let secret = self.secret.as_ref();
+        Ok(apply(
             self.cipher,
             Mode::Decrypt,
-            self.secret.as_ref(),
-            Some(&u128::from(index).to_le_bytes()),
-        )?;
-
-        let mut plaintext = [0u8; SIZE];
-        let update = crypter.update(&ciphertext, &mut plaintext)?;
-        let finalize = crypter.finalize(&mut plaintext[update..])?;
-        assert_eq!(update + finalize, SIZE);
-
-        Ok(plaintext)
+            secret,
+            index,
+            &ciphertext,
+        )?)
     }
 
     #[inline]
     fn set(&mut self, index: u64, block: &[u8; SIZE]) -> Result<(), Self::Error> {
-        let mut crypter = Crypter::new(
-            self.cipher,
-            Mode::Encrypt,
-            self.secret.as_ref(),
-            Some(&u128::from(index).to_le_bytes()),
-        )?;
+        let secret = self.secret.as_ref();
+        let ciphertext = apply(self.cipher, Mode::Encrypt, secret, index, block)?;
 
-        let mut ciphertext = [0u8; SIZE];
-        let update = crypter.update(block, &mut ciphertext)?;
-        let finalize = crypter.finalize(&mut ciphertext[update..])?;
-        assert_eq!(update + finalize, SIZE);
+        self.device.set(index, &ciphertext).map_err(Error::Parent)
+    }
 
-        match self.device.set(index, &ciphertext) {
-            Err(e) => return Err(Error::Parent(e)),
-            Ok(()) => Ok(()),
-        }
+    #[inline]
+    fn flush(&mut self) -> Result<(), Self::Error> {
+        self.device.flush().map_err(Error::Parent)
     }
 }
 
@@ -94,6 +141,8 @@ impl<T: Device<SIZE>, K: AsRef<[u8]>, const SIZE: usize> Device<SIZE> for Crypt<
 mod test {
     use super::*;
 
+    use quoin_device::Error as _;
+    use quoin_fault::{Fault, Operation, Plan};
     use quoin_memory::Memory;
     use rand::Rng;
 
@@ -116,4 +165,36 @@ mod test {
         let block = crypt.get(0).unwrap();
         assert_eq!(ZERO, block);
     }
+
+    #[test]
+    fn failure() {
+        let mut key = [0u8; 64];
+        rand::thread_rng().fill(&mut key);
+
+        let mut memory: Memory<512, 2> = Memory::default();
+        let plan = Plan::new(|_| std::io::Error::from(std::io::ErrorKind::TimedOut));
+        let fault = Fault::new(&mut memory, plan.clone());
+        let mut crypt = Crypt::new(fault, key, Cipher::aes_256_xts()).unwrap();
+
+        plan.fail_at(Operation::Get(0));
+        plan.fail_at(Operation::Set(1));
+        plan.fail_at(Operation::Flush);
+
+        let err = crypt.get(0).unwrap_err();
+        assert!(matches!(
+            err,
+            Error::Parent(quoin_fault::Error::Injected(..))
+        ));
+        assert_eq!(err.kind(), ErrorKind::Io);
+        assert!(matches!(crypt.set(1, &[1; 512]), Err(Error::Parent(..))));
+        assert!(matches!(crypt.flush(), Err(Error::Parent(..))));
+
+        plan.clear();
+        crypt.set(0, &[1; 512]).unwrap();
+        assert_eq!(crypt.get(0).unwrap(), [1; 512]);
+        drop(crypt);
+
+        // The failed write never reached the device.
+        assert_eq!(memory.get(1).unwrap(), [0; 512]);
+    }
 }
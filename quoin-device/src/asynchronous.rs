@@ -0,0 +1,102 @@
+use super::Error;
+
+use async_trait::async_trait;
+
+/// A device whose I/O is performed asynchronously
+///
+/// Blocks are passed by value and returned on success, so that a backend
+/// (e.g. io_uring) may own the buffer while the operation is in flight.
+#[async_trait]
+pub trait AsyncDevice<const SIZE: usize>: Send {
+    type Error: Error;
+
+    fn len(&self) -> u64;
+
+    #[inline]
+    fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Reads the block at `index` into `block`
+    async fn read(
+        &mut self,
+        index: u64,
+        block: Box<[u8; SIZE]>,
+    ) -> Result<Box<[u8; SIZE]>, Self::Error>;
+
+    /// Writes `block` to `index`
+    async fn write(
+        &mut self,
+        index: u64,
+        block: Box<[u8; SIZE]>,
+    ) -> Result<Box<[u8; SIZE]>, Self::Error>;
+
+    /// Ensures that all previous writes have reached stable storage
+    async fn flush(&mut self) -> Result<(), Self::Error>;
+}
+
+#[async_trait]
+impl<T: AsyncDevice<SIZE> + ?Sized, const SIZE: usize> AsyncDevice<SIZE> for &mut T {
+    type Error = T::Error;
+
+    #[inline]
+    fn len(&self) -> u64 {
+        (**self).len()
+    }
+
+    #[inline]
+    async fn read(
+        &mut self,
+        index: u64,
+        block: Box<[u8; SIZE]>,
+    ) -> Result<Box<[u8; SIZE]>, Self::Error> {
+        (**self).read(index, block).await
+    }
+
+    #[inline]
+    async fn write(
+        &mut self,
+        index: u64,
+        block: Box<[u8; SIZE]>,
+    ) -> Result<Box<[u8; SIZE]>, Self::Error> {
+        (**self).write(index, block).await
+    }
+
+    #[inline]
+    async fn flush(&mut self) -> Result<(), Self::Error> {
+        (**self).flush().await
+    }
+}
+
+#[async_trait]
+impl<T: AsyncDevice<SIZE> + ?Sized, const SIZE: usize> AsyncDevice<SIZE> for Box<T> {
+    type Error = T::Error;
+
+    #[inline]
+    fn len(&self) -> u64 {
+        (**self).len()
+    }
+
+    #[inline]
+    async fn read(
+        &mut self,
+        index: u64,
+        block: Box<[u8; SIZE]>,
+    ) -> Result<Box<[u8; SIZE]>, Self::Error> {
+        (**self).read(index, block).await
+    }
+
+    #[inline]
+    async fn write(
+        &mut self,
+        index: u64,
+        block: Box<[u8; SIZE]>,
+    ) -> Result<Box<[u8; SIZE]>, Self::Error> {
+        (**self).write(index, block).await
+    }
+
+    #[inline]
+    async fn flush(&mut self) -> Result<(), Self::Error> {
+        (**self).flush().await
+    }
+}
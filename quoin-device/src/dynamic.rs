@@ -0,0 +1,216 @@
+use super::{BlockSize, Device, ErrorKind};
+
+use std::error::Error;
+use std::fmt::{Debug, Display, Formatter};
+
+/// An error from a [DynDevice]
+///
+/// The [ErrorKind] of the original error is preserved.
+pub struct DynError {
+    error: Box<dyn Error + Send + Sync + 'static>,
+    kind: ErrorKind,
+}
+
+impl DynError {
+    #[inline]
+    pub fn new<E: super::Error>(error: E) -> Self {
+        Self {
+            kind: error.kind(),
+            error: Box::new(error),
+        }
+    }
+
+    /// Returns the underlying error, if it has type `E`
+    #[inline]
+    pub fn downcast_ref<E: Error + 'static>(&self) -> Option<&E> {
+        self.error.downcast_ref()
+    }
+
+    #[inline]
+    pub fn into_inner(self) -> Box<dyn Error + Send + Sync + 'static> {
+        self.error
+    }
+}
+
+impl Debug for DynError {
+    fn fmt(&self, f: &mut Formatter) -> std::fmt::Result {
+        Debug::fmt(&self.error, f)
+    }
+}
+
+impl Display for DynError {
+    fn fmt(&self, f: &mut Formatter) -> std::fmt::Result {
+        Display::fmt(&self.error, f)
+    }
+}
+
+impl Error for DynError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        self.error.source()
+    }
+}
+
+impl super::Error for DynError {
+    #[inline]
+    fn kind(&self) -> ErrorKind {
+        self.kind
+    }
+}
+
+/// An object-safe device whose block size is only known at runtime
+///
+/// Use [Dyn] to turn a [Device] into a [DynDevice] and [Fixed] to turn
+/// it back again.
+pub trait DynDevice {
+    fn block_size(&self) -> usize;
+    fn len(&self) -> u64;
+    fn get(&mut self, index: u64, block: &mut [u8]) -> Result<(), DynError>;
+    fn set(&mut self, index: u64, block: &[u8]) -> Result<(), DynError>;
+
+    #[inline]
+    fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Ensures that all previous writes have reached stable storage
+    #[inline]
+    fn flush(&mut self) -> Result<(), DynError> {
+        Ok(())
+    }
+}
+
+impl<T: DynDevice + ?Sized> DynDevice for Box<T> {
+    #[inline]
+    fn block_size(&self) -> usize {
+        (**self).block_size()
+    }
+
+    #[inline]
+    fn len(&self) -> u64 {
+        (**self).len()
+    }
+
+    #[inline]
+    fn get(&mut self, index: u64, block: &mut [u8]) -> Result<(), DynError> {
+        (**self).get(index, block)
+    }
+
+    #[inline]
+    fn set(&mut self, index: u64, block: &[u8]) -> Result<(), DynError> {
+        (**self).set(index, block)
+    }
+
+    #[inline]
+    fn flush(&mut self) -> Result<(), DynError> {
+        (**self).flush()
+    }
+}
+
+impl<T: DynDevice + ?Sized> DynDevice for &mut T {
+    #[inline]
+    fn block_size(&self) -> usize {
+        (**self).block_size()
+    }
+
+    #[inline]
+    fn len(&self) -> u64 {
+        (**self).len()
+    }
+
+    #[inline]
+    fn get(&mut self, index: u64, block: &mut [u8]) -> Result<(), DynError> {
+        (**self).get(index, block)
+    }
+
+    #[inline]
+    fn set(&mut self, index: u64, block: &[u8]) -> Result<(), DynError> {
+        (**self).set(index, block)
+    }
+
+    #[inline]
+    fn flush(&mut self) -> Result<(), DynError> {
+        (**self).flush()
+    }
+}
+
+/// Exposes a [Device] as a [DynDevice]
+pub struct Dyn<T: Device<SIZE>, const SIZE: usize>(pub T);
+
+impl<T: Device<SIZE>, const SIZE: usize> DynDevice for Dyn<T, SIZE> {
+    #[inline]
+    fn block_size(&self) -> usize {
+        SIZE
+    }
+
+    #[inline]
+    fn len(&self) -> u64 {
+        self.0.len()
+    }
+
+    #[inline]
+    fn get(&mut self, index: u64, block: &mut [u8]) -> Result<(), DynError> {
+        BlockSize::check(SIZE, block.len()).map_err(DynError::new)?;
+
+        block.copy_from_slice(&self.0.get(index).map_err(DynError::new)?);
+        Ok(())
+    }
+
+    #[inline]
+    fn set(&mut self, index: u64, block: &[u8]) -> Result<(), DynError> {
+        BlockSize::check(SIZE, block.len()).map_err(DynError::new)?;
+
+        let mut buffer = [0u8; SIZE];
+        buffer.copy_from_slice(block);
+        self.0.set(index, &buffer).map_err(DynError::new)
+    }
+
+    #[inline]
+    fn flush(&mut self) -> Result<(), DynError> {
+        self.0.flush().map_err(DynError::new)
+    }
+}
+
+/// Exposes a [DynDevice] with a block size of `SIZE` as a [Device]
+pub struct Fixed<T: DynDevice, const SIZE: usize>(T);
+
+impl<T: DynDevice, const SIZE: usize> Fixed<T, SIZE> {
+    /// Wraps the device, returning it if its block size is not `SIZE`
+    #[inline]
+    pub fn new(device: T) -> Result<Self, T> {
+        match device.block_size() {
+            size if size == SIZE => Ok(Self(device)),
+            _ => Err(device),
+        }
+    }
+
+    #[inline]
+    pub fn into_inner(self) -> T {
+        self.0
+    }
+}
+
+impl<T: DynDevice, const SIZE: usize> Device<SIZE> for Fixed<T, SIZE> {
+    type Error = DynError;
+
+    #[inline]
+    fn len(&self) -> u64 {
+        self.0.len()
+    }
+
+    #[inline]
+    fn get(&mut self, index: u64) -> Result<[u8; SIZE], Self::Error> {
+        let mut block = [0u8; SIZE];
+        self.0.get(index, &mut block)?;
+        Ok(block)
+    }
+
+    #[inline]
+    fn set(&mut self, index: u64, block: &[u8; SIZE]) -> Result<(), Self::Error> {
+        self.0.set(index, block)
+    }
+
+    #[inline]
+    fn flush(&mut self) -> Result<(), Self::Error> {
+        self.0.flush()
+    }
+}
@@ -0,0 +1,112 @@
+use std::convert::Infallible;
+use std::fmt::{Display, Formatter};
+
+/// The broad category of a device error
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum ErrorKind {
+    /// A block failed an integrity check
+    Integrity,
+
+    /// A block was only partially written
+    Tear,
+
+    /// The underlying storage failed
+    Io,
+
+    /// Anything else (e.g. invalid arguments or a cryptographic failure)
+    Other,
+}
+
+/// An error from any layer of a device stack
+///
+/// Layers that wrap another device report the kind of their parent's
+/// error, so the kind of the original failure is visible at any depth.
+pub trait Error: std::error::Error + Send + Sync + 'static {
+    fn kind(&self) -> ErrorKind;
+}
+
+impl Error for Infallible {
+    fn kind(&self) -> ErrorKind {
+        match *self {}
+    }
+}
+
+impl Error for std::io::Error {
+    fn kind(&self) -> ErrorKind {
+        ErrorKind::Io
+    }
+}
+
+/// An access to a block beyond the end of a device
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct OutOfBounds {
+    pub index: u64,
+    pub len: u64,
+}
+
+impl OutOfBounds {
+    /// Fails unless `index` is within a device of `len` blocks
+    #[inline]
+    pub fn check(index: u64, len: u64) -> Result<(), Self> {
+        match index < len {
+            true => Ok(()),
+            false => Err(Self { index, len }),
+        }
+    }
+}
+
+impl Display for OutOfBounds {
+    fn fmt(&self, f: &mut Formatter) -> std::fmt::Result {
+        write!(
+            f,
+            "block {} is out of bounds ({} blocks)",
+            self.index, self.len
+        )
+    }
+}
+
+impl std::error::Error for OutOfBounds {}
+
+impl Error for OutOfBounds {
+    #[inline]
+    fn kind(&self) -> ErrorKind {
+        ErrorKind::Other
+    }
+}
+
+/// A buffer that is not exactly one block long
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct BlockSize {
+    pub expected: usize,
+    pub actual: usize,
+}
+
+impl BlockSize {
+    /// Fails unless a buffer of `actual` bytes holds a block of `expected`
+    #[inline]
+    pub fn check(expected: usize, actual: usize) -> Result<(), Self> {
+        match expected == actual {
+            true => Ok(()),
+            false => Err(Self { expected, actual }),
+        }
+    }
+}
+
+impl Display for BlockSize {
+    fn fmt(&self, f: &mut Formatter) -> std::fmt::Result {
+        write!(
+            f,
+            "expected a block of {} bytes, not {}",
+            self.expected, self.actual
+        )
+    }
+}
+
+impl std::error::Error for BlockSize {}
+
+impl Error for BlockSize {
+    #[inline]
+    fn kind(&self) -> ErrorKind {
+        ErrorKind::Other
+    }
+}
@@ -1,12 +1,36 @@
+#[cfg(feature = "async")]
+mod asynchronous;
+mod dynamic;
+mod error;
+
+#[cfg(feature = "async")]
+pub use asynchronous::AsyncDevice;
+pub use dynamic::{Dyn, DynDevice, DynError, Fixed};
+pub use error::{BlockSize, Error, ErrorKind, OutOfBounds};
+
 pub trait Device<const SIZE: usize> {
-    type Error;
+    type Error: Error;
 
     fn len(&self) -> u64;
     fn get(&mut self, index: u64) -> Result<[u8; SIZE], Self::Error>;
     fn set(&mut self, index: u64, block: &[u8; SIZE]) -> Result<(), Self::Error>;
 
-    fn blocks(size: usize) -> u64 {
-        ((size + SIZE - 1) / SIZE) as u64
+    #[inline]
+    fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Ensures that all previous writes have reached stable storage
+    #[inline]
+    fn flush(&mut self) -> Result<(), Self::Error> {
+        Ok(())
+    }
+
+    fn blocks(size: usize) -> u64
+    where
+        Self: Sized,
+    {
+        size.div_ceil(SIZE) as u64
     }
 }
 
@@ -27,4 +51,84 @@ impl<T: Device<SIZE>, const SIZE: usize> Device<SIZE> for &mut T {
     fn set(&mut self, index: u64, block: &[u8; SIZE]) -> Result<(), Self::Error> {
         (**self).set(index, block)
     }
+
+    #[inline]
+    fn flush(&mut self) -> Result<(), Self::Error> {
+        (**self).flush()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    use std::io::Error;
+
+    // A device of four blocks that fails to read its last block
+    struct Blocks<const SIZE: usize>([[u8; SIZE]; 4]);
+
+    impl<const SIZE: usize> Device<SIZE> for Blocks<SIZE> {
+        type Error = Error;
+
+        fn len(&self) -> u64 {
+            self.0.len() as u64
+        }
+
+        fn get(&mut self, index: u64) -> Result<[u8; SIZE], Self::Error> {
+            match index {
+                3 => Err(std::io::ErrorKind::InvalidData.into()),
+                _ => Ok(self.0[index as usize]),
+            }
+        }
+
+        fn set(&mut self, index: u64, block: &[u8; SIZE]) -> Result<(), Self::Error> {
+            self.0[index as usize] = *block;
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn dynamic() {
+        let mut devices: Vec<Box<dyn DynDevice>> = vec![
+            Box::new(Dyn(Blocks([[0u8; 512]; 4]))),
+            Box::new(Dyn(Blocks([[0u8; 4096]; 4]))),
+        ];
+
+        for device in devices.iter_mut() {
+            let block = vec![7u8; device.block_size()];
+            device.set(1, &block).unwrap();
+
+            let mut check = vec![0u8; device.block_size()];
+            device.get(1, &mut check).unwrap();
+            assert_eq!(check, block);
+
+            let err = device.get(3, &mut check).unwrap_err();
+            assert_eq!(super::Error::kind(&err), ErrorKind::Io);
+            assert_eq!(
+                err.downcast_ref::<Error>().unwrap().kind(),
+                std::io::ErrorKind::InvalidData
+            );
+        }
+
+        let device = devices.pop().unwrap();
+        let device = Fixed::<_, 512>::new(device).err().unwrap();
+        let mut fixed = Fixed::<_, 4096>::new(device).ok().unwrap();
+        assert_eq!(fixed.len(), 4);
+        assert_eq!(fixed.get(1).unwrap(), [7u8; 4096]);
+
+        // Typed devices can be used as trait objects, too.
+        let device: &mut dyn Device<4096, Error = DynError> = &mut fixed;
+        assert!(device.get(3).is_err());
+
+        // Buffers of the wrong size are refused.
+        let mut device = fixed.into_inner();
+        let err = device.set(1, &[0u8; 512]).unwrap_err();
+        assert_eq!(
+            err.downcast_ref::<BlockSize>(),
+            Some(&BlockSize {
+                expected: 4096,
+                actual: 512
+            })
+        );
+    }
 }
@@ -0,0 +1,292 @@
+use quoin_device::{Device, ErrorKind};
+
+use std::collections::HashSet;
+use std::fmt::{Display, Formatter};
+use std::sync::{Arc, Mutex};
+
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+
+#[derive(Copy, Clone, Debug)]
+pub enum Error<T, E> {
+    /// The underlying device failed
+    Parent(T),
+
+    /// A fault was injected
+    Injected(E),
+}
+
+impl<T, E> Display for Error<T, E> {
+    fn fmt(&self, f: &mut Formatter) -> std::fmt::Result {
+        f.write_str(match self {
+            Self::Parent(..) => "device error",
+            Self::Injected(..) => "injected fault",
+        })
+    }
+}
+
+impl<T, E> std::error::Error for Error<T, E>
+where
+    T: std::error::Error + 'static,
+    E: std::error::Error + 'static,
+{
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Self::Parent(e) => Some(e),
+            Self::Injected(e) => Some(e),
+        }
+    }
+}
+
+impl<T: quoin_device::Error, E: quoin_device::Error> quoin_device::Error for Error<T, E> {
+    fn kind(&self) -> ErrorKind {
+        match self {
+            Self::Parent(e) => e.kind(),
+            Self::Injected(e) => e.kind(),
+        }
+    }
+}
+
+/// An operation on a device
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub enum Operation {
+    Get(u64),
+    Set(u64),
+    Flush,
+}
+
+struct Inner<E> {
+    error: Box<dyn FnMut(Operation) -> E + Send>,
+    at: HashSet<Operation>,
+    after: Option<u64>,
+    odds: f64,
+    random: StdRng,
+    operations: u64,
+    injected: u64,
+}
+
+/// The faults to inject into a [Fault] device
+///
+/// A plan is shared by its clones, so it can be changed while the device
+/// is owned by the layers above it. An operation fails if any of the
+/// configured faults applies to it. Failed operations do not reach the
+/// underlying device.
+pub struct Plan<E>(Arc<Mutex<Inner<E>>>);
+
+impl<E> Clone for Plan<E> {
+    fn clone(&self) -> Self {
+        Self(self.0.clone())
+    }
+}
+
+impl<E> Plan<E> {
+    /// Creates a plan with no faults, which calls `error` to create the
+    /// error for each fault
+    pub fn new(error: impl FnMut(Operation) -> E + Send + 'static) -> Self {
+        Self(Arc::new(Mutex::new(Inner {
+            error: Box::new(error),
+            at: HashSet::new(),
+            after: None,
+            odds: 0.0,
+            random: StdRng::from_entropy(),
+            operations: 0,
+            injected: 0,
+        })))
+    }
+
+    /// Fails every occurrence of `operation`
+    pub fn fail_at(&self, operation: Operation) {
+        self.0.lock().unwrap().at.insert(operation);
+    }
+
+    /// Fails every operation once `operations` more have been attempted
+    pub fn fail_after(&self, operations: u64) {
+        let mut inner = self.0.lock().unwrap();
+        inner.after = Some(inner.operations + operations);
+    }
+
+    /// Fails each operation with the given probability
+    ///
+    /// Panics unless `odds` is within `0.0..=1.0`, rather than on the next
+    /// operation with the plan's lock held.
+    pub fn set_odds(&self, odds: f64) {
+        assert!((0.0..=1.0).contains(&odds), "invalid odds: {}", odds);
+        self.0.lock().unwrap().odds = odds;
+    }
+
+    /// Makes the random faults reproducible
+    pub fn seed(&self, seed: u64) {
+        self.0.lock().unwrap().random = StdRng::seed_from_u64(seed);
+    }
+
+    /// Removes all faults
+    pub fn clear(&self) {
+        let mut inner = self.0.lock().unwrap();
+        inner.at.clear();
+        inner.after = None;
+        inner.odds = 0.0;
+    }
+
+    /// The number of operations attempted
+    pub fn operations(&self) -> u64 {
+        self.0.lock().unwrap().operations
+    }
+
+    /// The number of operations failed
+    pub fn injected(&self) -> u64 {
+        self.0.lock().unwrap().injected
+    }
+
+    fn check(&self, operation: Operation) -> Result<(), E> {
+        let mut inner = self.0.lock().unwrap();
+        let after = matches!(inner.after, Some(n) if inner.operations >= n);
+        inner.operations += 1;
+
+        let odds = inner.odds;
+        if after || inner.at.contains(&operation) || inner.random.gen_bool(odds) {
+            inner.injected += 1;
+            return Err((inner.error)(operation));
+        }
+
+        Ok(())
+    }
+}
+
+/// Fails operations on a device according to a plan
+pub struct Fault<T: Device<SIZE>, E, const SIZE: usize> {
+    device: T,
+    plan: Plan<E>,
+}
+
+impl<T: Device<SIZE>, E, const SIZE: usize> Fault<T, E, SIZE> {
+    pub fn new(device: T, plan: Plan<E>) -> Self {
+        Self { device, plan }
+    }
+}
+
+impl<T: Device<SIZE>, E, const SIZE: usize> Device<SIZE> for Fault<T, E, SIZE>
+where
+    E: quoin_device::Error,
+{
+    type Error = Error<T::Error, E>;
+
+    #[inline]
+    fn len(&self) -> u64 {
+        self.device.len()
+    }
+
+    #[inline]
+    fn get(&mut self, index: u64) -> Result<[u8; SIZE], Self::Error> {
+        let operation = Operation::Get(index);
+        self.plan.check(operation).map_err(Error::Injected)?;
+        self.device.get(index).map_err(Error::Parent)
+    }
+
+    #[inline]
+    fn set(&mut self, index: u64, block: &[u8; SIZE]) -> Result<(), Self::Error> {
+        let operation = Operation::Set(index);
+        self.plan.check(operation).map_err(Error::Injected)?;
+        self.device.set(index, block).map_err(Error::Parent)
+    }
+
+    #[inline]
+    fn flush(&mut self) -> Result<(), Self::Error> {
+        self.plan.check(Operation::Flush).map_err(Error::Injected)?;
+        self.device.flush().map_err(Error::Parent)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    use quoin_device::Error as _;
+    use quoin_memory::Memory;
+
+    fn plan() -> Plan<std::io::Error> {
+        Plan::new(|op| std::io::Error::new(std::io::ErrorKind::Interrupted, format!("{:?}", op)))
+    }
+
+    #[test]
+    fn at() {
+        let plan = plan();
+        let mut memory: Memory<512, 4> = Memory::default();
+        let mut fault = Fault::new(&mut memory, plan.clone());
+
+        plan.fail_at(Operation::Set(2));
+        plan.fail_at(Operation::Flush);
+        fault.set(1, &[1; 512]).unwrap();
+        fault.get(2).unwrap();
+
+        let err = fault.set(2, &[2; 512]).unwrap_err();
+        assert!(matches!(err, Error::Injected(ref e) if e.to_string() == "Set(2)"));
+        assert_eq!(err.kind(), ErrorKind::Io);
+        assert!(fault.flush().is_err());
+        assert!(fault.set(2, &[2; 512]).is_err());
+
+        // Parent errors pass through.
+        let err = fault.get(4).unwrap_err();
+        assert!(matches!(err, Error::Parent(..)));
+
+        assert_eq!(plan.operations(), 6);
+        assert_eq!(plan.injected(), 3);
+
+        plan.clear();
+        fault.set(2, &[2; 512]).unwrap();
+        fault.flush().unwrap();
+        assert_eq!(memory.get(1).unwrap(), [1; 512]);
+        assert_eq!(memory.get(2).unwrap(), [2; 512]);
+    }
+
+    #[test]
+    fn after() {
+        let plan = plan();
+        let mut memory: Memory<512, 4> = Memory::default();
+        let mut fault = Fault::new(&mut memory, plan.clone());
+
+        fault.get(0).unwrap();
+        plan.fail_after(2);
+        fault.set(0, &[1; 512]).unwrap();
+        fault.set(1, &[1; 512]).unwrap();
+        assert!(fault.set(2, &[1; 512]).is_err());
+        assert!(fault.get(0).is_err());
+        assert_eq!(plan.injected(), 2);
+
+        assert_eq!(memory.get(1).unwrap(), [1; 512]);
+        assert_eq!(memory.get(2).unwrap(), [0; 512]);
+    }
+
+    #[test]
+    fn odds() {
+        const TOTAL: usize = 10_000;
+
+        let plan = plan();
+        let mut memory: Memory<512, 1> = Memory::default();
+        let mut fault = Fault::new(&mut memory, plan.clone());
+
+        plan.seed(1);
+        plan.set_odds(0.1);
+        let first: Vec<bool> = (0..TOTAL).map(|_| fault.get(0).is_err()).collect();
+
+        let percent = plan.injected() as f64 / TOTAL as f64;
+        assert!(percent > 0.09);
+        assert!(percent < 0.11);
+
+        plan.seed(1);
+        let second: Vec<bool> = (0..TOTAL).map(|_| fault.get(0).is_err()).collect();
+        assert_eq!(first, second);
+    }
+
+    #[test]
+    fn invalid_odds() {
+        let plan = plan();
+        for odds in [-0.1, 1.1, f64::NAN] {
+            let plan = plan.clone();
+            let result = std::panic::catch_unwind(move || plan.set_odds(odds));
+            assert!(result.is_err());
+        }
+
+        // The plan is still usable.
+        plan.set_odds(1.0);
+    }
+}
@@ -0,0 +1,101 @@
+use std::fs::read_to_string;
+use std::io::Result;
+use std::os::raw::{c_int, c_uint, c_ushort};
+use std::os::unix::fs::{FileTypeExt, MetadataExt};
+
+use iocuddle::*;
+
+const BLOCK: Group = Group::new(0x12);
+const BLKROGET: Ioctl<Read, &c_int> = unsafe { BLOCK.none(94) };
+const BLKSSZGET: Ioctl<Read, &c_uint> = unsafe { BLOCK.none(104) };
+const BLKGETSIZE64: Ioctl<Read, &u64> = unsafe { BLOCK.read::<usize>(114).lie() };
+const BLKIOOPT: Ioctl<Read, &c_uint> = unsafe { BLOCK.none(121) };
+const BLKALIGNOFF: Ioctl<Read, &c_int> = unsafe { BLOCK.none(122) };
+const BLKPBSZGET: Ioctl<Read, &c_uint> = unsafe { BLOCK.none(123) };
+const BLKROTATIONAL: Ioctl<Read, &c_ushort> = unsafe { BLOCK.none(126) };
+
+/// The geometry and capabilities of a file or block device
+///
+/// For regular files, the values describe the file system: files can be
+/// addressed by the byte and do not support discard or write zeroes.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct Geometry {
+    /// The size in bytes
+    pub size: u64,
+
+    /// The smallest unit the device can address
+    pub logical_block_size: u32,
+
+    /// The smallest unit the device can write without read-modify-write
+    pub physical_block_size: u32,
+
+    /// The offset of the first physically aligned block
+    pub alignment_offset: u32,
+
+    /// The preferred size of large I/O (zero if not reported)
+    pub optimal_io_size: u32,
+
+    /// Whether the device has rotating media
+    pub rotational: bool,
+
+    /// The unit in which blocks are discarded (zero if not supported)
+    pub discard_granularity: u32,
+
+    /// Whether the device is read-only (see `BLKROGET`)
+    ///
+    /// This is always false for regular files, whose permission bits do not
+    /// say whether this process can write them.
+    pub read_only: bool,
+
+    /// Whether the device can write zeroes without transferring them
+    pub write_zeroes: bool,
+}
+
+// Reads a queue attribute of a block device (or of the disk of a partition).
+fn queue(rdev: u64, name: &str) -> Option<u64> {
+    // See `major()` and `minor()` in glibc.
+    let major = ((rdev >> 8) & 0xfff) | ((rdev >> 32) & !0xfff);
+    let minor = (rdev & 0xff) | ((rdev >> 12) & !0xff);
+
+    ["queue", "../queue"].iter().find_map(|dir| {
+        let path = format!("/sys/dev/block/{}:{}/{}/{}", major, minor, dir, name);
+        read_to_string(path).ok()?.trim().parse().ok()
+    })
+}
+
+impl Geometry {
+    pub(crate) fn probe(file: &mut std::fs::File) -> Result<Self> {
+        let metadata = file.metadata()?;
+
+        // Only block devices support the block ioctls.
+        if !metadata.file_type().is_block_device() {
+            return Ok(Self {
+                size: metadata.len(),
+                logical_block_size: 1,
+                physical_block_size: metadata.blksize() as u32,
+                alignment_offset: 0,
+                optimal_io_size: metadata.blksize() as u32,
+                rotational: false,
+                discard_granularity: 0,
+                read_only: false,
+                write_zeroes: false,
+            });
+        }
+
+        let rdev = metadata.rdev();
+        let discard = queue(rdev, "discard_granularity").unwrap_or(0);
+        let zeroes = queue(rdev, "write_zeroes_max_bytes").unwrap_or(0);
+
+        Ok(Self {
+            size: BLKGETSIZE64.ioctl(&mut *file)?.1,
+            logical_block_size: BLKSSZGET.ioctl(&mut *file)?.1,
+            physical_block_size: BLKPBSZGET.ioctl(&mut *file)?.1,
+            alignment_offset: BLKALIGNOFF.ioctl(&mut *file)?.1 as u32,
+            optimal_io_size: BLKIOOPT.ioctl(&mut *file)?.1,
+            rotational: BLKROTATIONAL.ioctl(&mut *file)?.1 != 0,
+            discard_granularity: discard as u32,
+            read_only: BLKROGET.ioctl(&mut *file)?.1 != 0,
+            write_zeroes: zeroes > 0,
+        })
+    }
+}
@@ -1,42 +1,139 @@
+mod geometry;
+#[cfg(feature = "mmap")]
+mod mmap;
+mod options;
+#[cfg(feature = "uring")]
+mod uring;
+
+pub use geometry::Geometry;
+#[cfg(feature = "mmap")]
+pub use mmap::Mmap;
+pub use options::OpenOptions;
+#[cfg(feature = "uring")]
+pub use uring::Uring;
+
 use quoin_device::Device;
 
 use std::convert::TryFrom;
 use std::io::{ErrorKind, Write};
-use std::os::raw::c_uint;
 use std::os::unix::fs::FileExt;
+use std::os::unix::io::AsRawFd;
+use std::path::Path;
 
-use iocuddle::*;
-
-const BLOCK: Group = Group::new(0x12);
-//const BLKGETSIZE: Ioctl<Read, &c_long> = unsafe { BLOCK.none(96) };
-//const BLKSSZGET: Ioctl<Read, &c_uint> = unsafe { BLOCK.none(104) };
-//const BLKBSZGET: Ioctl<Read, &c_uint> = unsafe { BLOCK.read::<usize>(112).lie() };
-const BLKGETSIZE64: Ioctl<Read, &u64> = unsafe { BLOCK.read::<usize>(114).lie() };
-const BLKPBSZGET: Ioctl<Read, &c_uint> = unsafe { BLOCK.none(123) };
+// A block buffer suitably aligned for `O_DIRECT`
+#[repr(align(4096))]
+struct Aligned<const SIZE: usize>([u8; SIZE]);
 
 pub struct File<const SIZE: usize> {
     file: std::fs::File,
     size: u64,
+    buffer: Option<Box<Aligned<SIZE>>>,
+    geometry: Geometry,
+    read_only: bool,
+}
+
+impl<const SIZE: usize> File<SIZE> {
+    #[inline]
+    pub fn geometry(&self) -> &Geometry {
+        &self.geometry
+    }
+
+    /// Whether writes are refused
+    ///
+    /// This is the case if the file was opened read-only or if the device
+    /// itself is read-only.
+    #[inline]
+    pub fn is_read_only(&self) -> bool {
+        self.read_only
+    }
+
+    /// Creates a new sparse image of `blocks` blocks
+    ///
+    /// See [OpenOptions::create] for more control.
+    pub fn create<P: AsRef<Path>>(path: P, blocks: u64) -> Result<Self, std::io::Error> {
+        OpenOptions::new().create(blocks).open(path)
+    }
+
+    /// Grows or truncates the image to `blocks` blocks
+    ///
+    /// Growth is sparse. Block devices cannot be resized.
+    pub fn resize(&mut self, blocks: u64) -> Result<(), std::io::Error> {
+        self.writable()?;
+
+        if !self.file.metadata()?.is_file() {
+            return Err(ErrorKind::Unsupported.into());
+        }
+
+        let size = blocks.checked_mul(SIZE as u64);
+        let size = size.ok_or(ErrorKind::InvalidInput)?;
+        self.file.set_len(size)?;
+        self.geometry.size = size;
+        self.size = blocks;
+        Ok(())
+    }
+
+    /// The number of bytes backed by storage, found by walking the
+    /// allocated extents with `SEEK_DATA`/`SEEK_HOLE`
+    ///
+    /// Devices without hole support are reported as fully allocated.
+    pub fn allocated(&self) -> Result<u64, std::io::Error> {
+        let fd = self.file.as_raw_fd();
+        let mut allocated = 0;
+        let mut offset = 0;
+
+        loop {
+            let data = unsafe { libc::lseek(fd, offset, libc::SEEK_DATA) };
+            if data < 0 {
+                let error = std::io::Error::last_os_error();
+                return match error.raw_os_error() {
+                    Some(libc::ENXIO) => Ok(allocated),
+                    Some(libc::EINVAL) if offset == 0 => Ok(self.geometry.size),
+                    _ => Err(error),
+                };
+            }
+
+            let hole = unsafe { libc::lseek(fd, data, libc::SEEK_HOLE) };
+            if hole < 0 {
+                return Err(std::io::Error::last_os_error());
+            }
+
+            allocated += (hole - data) as u64;
+            offset = hole;
+        }
+    }
+
+    fn writable(&self) -> Result<(), std::io::Error> {
+        match self.read_only {
+            true => Err(ErrorKind::PermissionDenied.into()),
+            false => Ok(()),
+        }
+    }
 }
 
 impl<const SIZE: usize> TryFrom<std::fs::File> for File<SIZE> {
     type Error = std::io::Error;
 
     fn try_from(mut file: std::fs::File) -> Result<Self, Self::Error> {
-        let size = match BLKPBSZGET.ioctl(&mut file) {
-            Ok((_, phys_block_size)) => {
-                if phys_block_size as usize != SIZE {
-                    return Err(ErrorKind::InvalidInput.into());
-                }
+        let geometry = Geometry::probe(&mut file)?;
 
-                BLKGETSIZE64.ioctl(&mut file)?.1 / SIZE as u64
-            }
+        // The device must be able to address each block. Blocks smaller
+        // than the physical block size work, but need read-modify-write.
+        if SIZE == 0 || !SIZE.is_multiple_of(geometry.logical_block_size as usize) {
+            return Err(ErrorKind::InvalidInput.into());
+        }
 
-            Err(e) if e.kind() == ErrorKind::Other => file.metadata()?.len() / SIZE as u64,
-            Err(e) => return Err(e),
-        };
+        let flags = unsafe { libc::fcntl(file.as_raw_fd(), libc::F_GETFL) };
+        if flags < 0 {
+            return Err(std::io::Error::last_os_error());
+        }
 
-        Ok(Self { file, size })
+        Ok(Self {
+            read_only: geometry.read_only || flags & libc::O_ACCMODE == libc::O_RDONLY,
+            file,
+            size: geometry.size / SIZE as u64,
+            buffer: None,
+            geometry,
+        })
     }
 }
 
@@ -51,6 +148,15 @@ impl<const SIZE: usize> Device<SIZE> for File<SIZE> {
     #[inline]
     fn set(&mut self, index: u64, block: &[u8; SIZE]) -> Result<(), Self::Error> {
         assert!(index < self.size);
+        self.writable()?;
+
+        let block = match self.buffer {
+            Some(ref mut buffer) => {
+                buffer.0.copy_from_slice(block);
+                &buffer.0
+            }
+            None => block,
+        };
 
         self.file.write_all_at(block, index * SIZE as u64)?;
         self.file.flush()
@@ -60,8 +166,161 @@ impl<const SIZE: usize> Device<SIZE> for File<SIZE> {
     fn get(&mut self, index: u64) -> Result<[u8; SIZE], Self::Error> {
         assert!(index < self.size);
 
+        if let Some(ref mut buffer) = self.buffer {
+            self.file
+                .read_exact_at(&mut buffer.0, index * SIZE as u64)?;
+            return Ok(buffer.0);
+        }
+
         let mut block = [0; SIZE];
         self.file.read_exact_at(&mut block, index * SIZE as u64)?;
         Ok(block)
     }
+
+    #[inline]
+    fn flush(&mut self) -> Result<(), Self::Error> {
+        self.file.sync_data()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    use std::os::unix::fs::PermissionsExt;
+
+    #[test]
+    fn options() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("image");
+        std::fs::File::create(&path)
+            .unwrap()
+            .set_len(8 * 4096)
+            .unwrap();
+
+        let mut options = OpenOptions::new();
+        options.direct(true).dsync(true);
+        let mut direct: File<4096> = match options.open(&path) {
+            Err(e) if e.kind() == ErrorKind::InvalidInput => {
+                eprintln!("O_DIRECT is unsupported; skipping");
+                return;
+            }
+            result => result.unwrap(),
+        };
+
+        assert_eq!(direct.len(), 8);
+        assert_eq!(direct.geometry().size, 8 * 4096);
+        assert_eq!(direct.geometry().logical_block_size, 1);
+        assert!(!direct.geometry().read_only);
+        direct.set(3, &[0xaa; 4096]).unwrap();
+        assert_eq!(direct.get(3).unwrap(), [0xaa; 4096]);
+        direct.flush().unwrap();
+        drop(direct);
+
+        // Direct I/O needs blocks of at least a sector.
+        let err = options.open::<_, 256>(&path).err();
+        assert_eq!(err.map(|e| e.kind()), Some(ErrorKind::InvalidInput));
+
+        let mut cached: File<4096> = OpenOptions::new().open(&path).unwrap();
+        assert_eq!(cached.get(3).unwrap(), [0xaa; 4096]);
+
+        let err = OpenOptions::new().open::<_, 512>("/nonexistent").err();
+        assert_eq!(err.map(|e| e.kind()), Some(ErrorKind::NotFound));
+    }
+
+    #[test]
+    fn lock() {
+        let file = tempfile::NamedTempFile::new().unwrap();
+        file.as_file().set_len(8 * 512).unwrap();
+
+        let mut options = OpenOptions::new();
+        let writer: File<512> = options.open(file.path()).unwrap();
+        let err = options.open::<_, 512>(file.path()).err();
+        assert_eq!(err.map(|e| e.kind()), Some(ErrorKind::WouldBlock));
+        let err = options.read_only(true).open::<_, 512>(file.path()).err();
+        assert_eq!(err.map(|e| e.kind()), Some(ErrorKind::WouldBlock));
+        drop(writer);
+
+        // Readers share the lock, but exclude writers.
+        let reader: File<512> = options.open(file.path()).unwrap();
+        let _reader: File<512> = options.open(file.path()).unwrap();
+        let err = options.read_only(false).open::<_, 512>(file.path()).err();
+        assert_eq!(err.map(|e| e.kind()), Some(ErrorKind::WouldBlock));
+        options.lock(false).open::<_, 512>(file.path()).unwrap();
+        drop(reader);
+    }
+
+    #[test]
+    fn read_only() {
+        let file = tempfile::NamedTempFile::new().unwrap();
+        file.as_file().set_len(8 * 512).unwrap();
+
+        let mut writer: File<512> = OpenOptions::new().open(file.path()).unwrap();
+        assert!(!writer.is_read_only());
+        writer.set(1, &[0x55; 512]).unwrap();
+        drop(writer);
+
+        let mut reader: File<512> = OpenOptions::new()
+            .read_only(true)
+            .open(file.path())
+            .unwrap();
+        assert!(reader.is_read_only());
+        assert_eq!(reader.get(1).unwrap(), [0x55; 512]);
+        let err = reader.set(1, &[0xaa; 512]).unwrap_err();
+        assert_eq!(err.kind(), ErrorKind::PermissionDenied);
+        assert_eq!(reader.get(1).unwrap(), [0x55; 512]);
+
+        // A read-only descriptor is detected without the options.
+        let reader = std::fs::File::open(file.path()).unwrap();
+        assert!(File::<512>::try_from(reader).unwrap().is_read_only());
+
+        // A writable descriptor can write, whatever the mode bits say.
+        let writer = std::fs::OpenOptions::new()
+            .read(true)
+            .write(true)
+            .open(file.path())
+            .unwrap();
+        let readable = std::fs::Permissions::from_mode(0o444);
+        std::fs::set_permissions(file.path(), readable).unwrap();
+        let mut writer = File::<512>::try_from(writer).unwrap();
+        assert!(!writer.is_read_only());
+        assert!(!writer.geometry().read_only);
+        writer.set(1, &[0xaa; 512]).unwrap();
+        assert_eq!(writer.get(1).unwrap(), [0xaa; 512]);
+    }
+
+    #[test]
+    fn sparse() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("image");
+
+        let mut file = File::<4096>::create(&path, 256).unwrap();
+        assert_eq!(file.len(), 256);
+        assert_eq!(file.geometry().size, 256 * 4096);
+        assert_eq!(file.allocated().unwrap(), 0);
+
+        file.set(100, &[0xaa; 4096]).unwrap();
+        let allocated = file.allocated().unwrap();
+        assert!((4096..256 * 4096).contains(&allocated));
+
+        file.resize(1024).unwrap();
+        assert_eq!(file.len(), 1024);
+        assert_eq!(std::fs::metadata(&path).unwrap().len(), 1024 * 4096);
+        assert_eq!(file.allocated().unwrap(), allocated);
+        assert_eq!(file.get(100).unwrap(), [0xaa; 4096]);
+        assert_eq!(file.get(1000).unwrap(), [0; 4096]);
+
+        file.resize(64).unwrap();
+        assert_eq!(file.len(), 64);
+        assert_eq!(file.allocated().unwrap(), 0);
+        drop(file);
+
+        let err = File::<4096>::create(&path, 1).err();
+        assert_eq!(err.map(|e| e.kind()), Some(ErrorKind::AlreadyExists));
+
+        let mut reader: File<4096> = OpenOptions::new().read_only(true).open(&path).unwrap();
+        assert_eq!(reader.len(), 64);
+        let err = reader.resize(128).unwrap_err();
+        assert_eq!(err.kind(), ErrorKind::PermissionDenied);
+    }
 }
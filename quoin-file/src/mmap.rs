@@ -0,0 +1,137 @@
+use super::File;
+
+use quoin_device::Device;
+
+use std::convert::TryFrom;
+use std::io::{Error, ErrorKind, Result};
+
+use memmap2::{MmapMut, MmapOptions};
+
+enum Map {
+    Read(memmap2::Mmap),
+    Write(MmapMut),
+}
+
+/// A file or block device accessed through a shared memory mapping
+///
+/// Blocks are copied to and from the mapping, and [Device::flush] writes
+/// dirty pages back with `msync`. Modifying the file by other means while
+/// it is mapped is undefined behavior, so open it with the (default)
+/// locking of [crate::OpenOptions].
+pub struct Mmap<const SIZE: usize> {
+    map: Map,
+    file: File<SIZE>,
+}
+
+impl<const SIZE: usize> Mmap<SIZE> {
+    #[inline]
+    fn offset(&self, index: u64) -> usize {
+        assert!(index < self.file.size);
+        index as usize * SIZE
+    }
+}
+
+impl<const SIZE: usize> TryFrom<File<SIZE>> for Mmap<SIZE> {
+    type Error = Error;
+
+    fn try_from(file: File<SIZE>) -> Result<Self> {
+        let mut options = MmapOptions::new();
+        options.len((file.size * SIZE as u64) as usize);
+
+        let map = match file.read_only {
+            true => Map::Read(unsafe { options.map(&file.file)? }),
+            false => Map::Write(unsafe { options.map_mut(&file.file)? }),
+        };
+
+        Ok(Self { map, file })
+    }
+}
+
+impl<const SIZE: usize> TryFrom<std::fs::File> for Mmap<SIZE> {
+    type Error = Error;
+
+    #[inline]
+    fn try_from(file: std::fs::File) -> Result<Self> {
+        Self::try_from(File::try_from(file)?)
+    }
+}
+
+impl<const SIZE: usize> Device<SIZE> for Mmap<SIZE> {
+    type Error = Error;
+
+    #[inline]
+    fn len(&self) -> u64 {
+        self.file.size
+    }
+
+    #[inline]
+    fn get(&mut self, index: u64) -> Result<[u8; SIZE]> {
+        let offset = self.offset(index);
+        let map = match self.map {
+            Map::Read(ref map) => &map[..],
+            Map::Write(ref map) => &map[..],
+        };
+
+        let mut block = [0; SIZE];
+        block.copy_from_slice(&map[offset..][..SIZE]);
+        Ok(block)
+    }
+
+    #[inline]
+    fn set(&mut self, index: u64, block: &[u8; SIZE]) -> Result<()> {
+        let offset = self.offset(index);
+        match self.map {
+            Map::Read(..) => Err(ErrorKind::PermissionDenied.into()),
+            Map::Write(ref mut map) => {
+                map[offset..][..SIZE].copy_from_slice(block);
+                Ok(())
+            }
+        }
+    }
+
+    #[inline]
+    fn flush(&mut self) -> Result<()> {
+        match self.map {
+            Map::Read(..) => Ok(()),
+            Map::Write(ref map) => map.flush(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    use crate::OpenOptions;
+
+    #[test]
+    fn mmap() {
+        let file = tempfile::NamedTempFile::new().unwrap();
+        file.as_file().set_len(32 * 512).unwrap();
+
+        let mut device = Mmap::<512>::try_from(file.reopen().unwrap()).unwrap();
+        assert_eq!(device.len(), 32);
+        assert_eq!(device.get(31).unwrap(), [0; 512]);
+
+        device.set(31, &[0xaa; 512]).unwrap();
+        device.set(0, &[0x55; 512]).unwrap();
+        assert_eq!(device.get(31).unwrap(), [0xaa; 512]);
+        device.flush().unwrap();
+        drop(device);
+
+        let mut cached = File::<512>::try_from(file.reopen().unwrap()).unwrap();
+        assert_eq!(cached.get(0).unwrap(), [0x55; 512]);
+        assert_eq!(cached.get(31).unwrap(), [0xaa; 512]);
+        drop(cached);
+
+        let reader: File<512> = OpenOptions::new()
+            .read_only(true)
+            .open(file.path())
+            .unwrap();
+        let mut reader = Mmap::try_from(reader).unwrap();
+        assert_eq!(reader.get(31).unwrap(), [0xaa; 512]);
+        let err = reader.set(31, &[0; 512]).unwrap_err();
+        assert_eq!(err.kind(), ErrorKind::PermissionDenied);
+        reader.flush().unwrap();
+    }
+}
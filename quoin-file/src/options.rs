@@ -0,0 +1,167 @@
+use super::{Aligned, File};
+
+use std::convert::TryFrom;
+use std::io::{Error, ErrorKind, Result};
+use std::os::unix::fs::{FileTypeExt, MetadataExt, OpenOptionsExt};
+use std::os::unix::io::AsRawFd;
+use std::path::Path;
+
+/// Options for opening a [File], in the manner of [std::fs::OpenOptions]
+///
+/// By default, the file is opened for reading and writing and is locked.
+#[derive(Copy, Clone, Debug)]
+pub struct OpenOptions {
+    direct: bool,
+    dsync: bool,
+    read_only: bool,
+    lock: bool,
+    create: Option<u64>,
+}
+
+impl Default for OpenOptions {
+    fn default() -> Self {
+        Self {
+            direct: false,
+            dsync: false,
+            read_only: false,
+            lock: true,
+            create: None,
+        }
+    }
+}
+
+impl OpenOptions {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Opens the file for reading only
+    ///
+    /// Writes to the resulting [File] fail with `PermissionDenied`.
+    pub fn read_only(&mut self, read_only: bool) -> &mut Self {
+        self.read_only = read_only;
+        self
+    }
+
+    /// Creates a new sparse image of `blocks` blocks
+    ///
+    /// Opening fails with `AlreadyExists` if the file exists.
+    pub fn create(&mut self, blocks: u64) -> &mut Self {
+        self.create = Some(blocks);
+        self
+    }
+
+    /// Takes an advisory lock (`flock`) for as long as the [File] is open
+    ///
+    /// The lock is exclusive when writing and shared when read-only. If
+    /// another process holds a conflicting lock, opening fails with
+    /// `WouldBlock`. Block devices opened for writing are also opened with
+    /// `O_EXCL`, so that they cannot be opened while mounted.
+    pub fn lock(&mut self, lock: bool) -> &mut Self {
+        self.lock = lock;
+        self
+    }
+
+    /// Bypasses the page cache (`O_DIRECT`)
+    ///
+    /// Blocks are transferred through an aligned buffer, as required.
+    /// Opening fails with `InvalidInput` if the block size is not a
+    /// multiple of the file's direct I/O alignment.
+    pub fn direct(&mut self, direct: bool) -> &mut Self {
+        self.direct = direct;
+        self
+    }
+
+    /// Completes each write only once it reaches stable storage (`O_DSYNC`)
+    pub fn dsync(&mut self, dsync: bool) -> &mut Self {
+        self.dsync = dsync;
+        self
+    }
+
+    pub fn open<P: AsRef<Path>, const SIZE: usize>(&self, path: P) -> Result<File<SIZE>> {
+        let mut flags = 0;
+        if self.direct {
+            flags |= libc::O_DIRECT;
+        }
+        if self.dsync {
+            flags |= libc::O_DSYNC;
+        }
+
+        let path = path.as_ref();
+        let exclusive = self.lock && !self.read_only && self.create.is_none();
+        if exclusive && std::fs::metadata(path)?.file_type().is_block_device() {
+            flags |= libc::O_EXCL;
+        }
+
+        let file = std::fs::OpenOptions::new()
+            .read(true)
+            .write(!self.read_only)
+            .create_new(self.create.is_some())
+            .custom_flags(flags)
+            .open(path)?;
+
+        if self.lock {
+            let operation = match self.read_only {
+                true => libc::LOCK_SH,
+                false => libc::LOCK_EX,
+            };
+
+            if unsafe { libc::flock(file.as_raw_fd(), operation | libc::LOCK_NB) } < 0 {
+                return Err(Error::last_os_error());
+            }
+        }
+
+        if let Some(blocks) = self.create {
+            let size = blocks.checked_mul(SIZE as u64);
+            file.set_len(size.ok_or(ErrorKind::InvalidInput)?)?;
+        }
+
+        let mut file = File::try_from(file)?;
+
+        // A read-only device (see `BLKROGET`) may still be opened for
+        // writing, so refuse it up front rather than on the first write.
+        if !self.read_only && file.geometry().read_only {
+            return Err(ErrorKind::PermissionDenied.into());
+        }
+
+        if self.direct {
+            let (offset, memory) = alignment(&file.file, file.geometry().logical_block_size)?;
+            let buffer = std::mem::align_of::<Aligned<SIZE>>() as u64;
+            if !(SIZE as u64).is_multiple_of(offset) || memory > buffer {
+                return Err(ErrorKind::InvalidInput.into());
+            }
+
+            file.buffer = Some(Box::new(Aligned([0; SIZE])));
+        }
+
+        Ok(file)
+    }
+}
+
+// Finds the offset and memory alignment required for direct I/O.
+//
+// Kernels without `STATX_DIOALIGN` report neither, so assume the logical
+// block size of a block device or the preferred I/O size of a regular file.
+fn alignment(file: &std::fs::File, logical: u32) -> Result<(u64, u64)> {
+    let mut stx: libc::statx = unsafe { std::mem::zeroed() };
+    let path = b"\0".as_ptr().cast();
+    let flags = libc::AT_EMPTY_PATH;
+    let mask = libc::STATX_DIOALIGN;
+    if unsafe { libc::statx(file.as_raw_fd(), path, flags, mask, &mut stx) } == 0
+        && stx.stx_mask & mask != 0
+    {
+        // Files that do not support direct I/O report no alignment.
+        return match stx.stx_dio_offset_align {
+            0 => Err(ErrorKind::InvalidInput.into()),
+            offset => Ok((offset.into(), stx.stx_dio_mem_align.into())),
+        };
+    }
+
+    let metadata = file.metadata()?;
+    let align = match metadata.file_type().is_block_device() {
+        true => logical.into(),
+        false => metadata.blksize(),
+    };
+
+    Ok((align, align))
+}
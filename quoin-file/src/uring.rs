@@ -0,0 +1,276 @@
+use super::File;
+
+use quoin_device::Device;
+
+use std::convert::TryFrom;
+use std::io::{Error, ErrorKind, Result};
+use std::os::unix::fs::FileExt;
+use std::os::unix::io::AsRawFd;
+
+use io_uring::{opcode, squeue, types, EnterFlags, IoUring};
+
+/// A file or block device accessed through io_uring
+///
+/// Multi-block operations are submitted together, with up to `depth` blocks
+/// in flight at once. If the running kernel does not support io_uring, or
+/// if io_uring itself fails, the device falls back to `pread`/`pwrite`.
+pub struct Uring<const SIZE: usize> {
+    file: File<SIZE>,
+    ring: Option<IoUring>,
+    depth: usize,
+}
+
+impl<const SIZE: usize> Uring<SIZE> {
+    pub fn new(file: std::fs::File, depth: u32) -> Result<Self> {
+        assert!(depth > 0);
+
+        Ok(Self {
+            file: File::try_from(file)?,
+            ring: IoUring::new(depth).ok(),
+            depth: depth as usize,
+        })
+    }
+
+    /// Whether the device is using io_uring (rather than the fallback)
+    #[inline]
+    pub fn is_uring(&self) -> bool {
+        self.ring.is_some()
+    }
+
+    /// Reads consecutive blocks, starting at `index`
+    pub fn read_blocks(&mut self, index: u64, blocks: &mut [[u8; SIZE]]) -> Result<()> {
+        assert!(index + blocks.len() as u64 <= self.file.size);
+
+        if self.ring.is_none() {
+            for (i, block) in blocks.iter_mut().enumerate() {
+                let offset = (index + i as u64) * SIZE as u64;
+                self.file.file.read_exact_at(block, offset)?;
+            }
+
+            return Ok(());
+        }
+
+        let fd = types::Fd(self.file.file.as_raw_fd());
+        let entries = blocks.iter_mut().enumerate().map(|(i, block)| {
+            opcode::Read::new(fd, block.as_mut_ptr(), SIZE as u32)
+                .offset((index + i as u64) * SIZE as u64)
+                .build()
+        });
+
+        self.submit(entries, |n| {
+            transferred::<SIZE>(n, ErrorKind::UnexpectedEof)
+        })
+    }
+
+    /// Writes consecutive blocks, starting at `index`
+    pub fn write_blocks(&mut self, index: u64, blocks: &[[u8; SIZE]]) -> Result<()> {
+        assert!(index + blocks.len() as u64 <= self.file.size);
+        self.file.writable()?;
+
+        if self.ring.is_none() {
+            for (i, block) in blocks.iter().enumerate() {
+                let offset = (index + i as u64) * SIZE as u64;
+                self.file.file.write_all_at(block, offset)?;
+            }
+
+            return Ok(());
+        }
+
+        let fd = types::Fd(self.file.file.as_raw_fd());
+        let entries = blocks.iter().enumerate().map(|(i, block)| {
+            opcode::Write::new(fd, block.as_ptr(), SIZE as u32)
+                .offset((index + i as u64) * SIZE as u64)
+                .build()
+        });
+
+        self.submit(entries, |n| transferred::<SIZE>(n, ErrorKind::WriteZero))
+    }
+
+    // Runs each operation, checking its result with `check`.
+    //
+    // The buffers of the operations are borrowed by the caller, so this must
+    // not return until every submitted operation has completed, even after
+    // an error. If io_uring itself fails, the operations it has not taken
+    // are discarded with the ring, and the device falls back to
+    // `pread`/`pwrite` from then on.
+    fn submit(
+        &mut self,
+        mut entries: impl Iterator<Item = squeue::Entry>,
+        check: impl Fn(i32) -> Result<()>,
+    ) -> Result<()> {
+        let ring = self.ring.as_mut().unwrap();
+        let mut result = Ok(());
+
+        // Operations pushed, but not yet completed
+        let mut pending = 0;
+
+        let error = loop {
+            // Stop submitting after the first failure.
+            while result.is_ok() && pending < self.depth {
+                let entry = match entries.next() {
+                    Some(entry) => entry,
+                    None => break,
+                };
+
+                // The queue holds `depth` entries, so there is always room.
+                unsafe { ring.submission().push(&entry).unwrap() };
+                pending += 1;
+            }
+
+            if pending == 0 {
+                return result;
+            }
+
+            match ring.submit_and_wait(1) {
+                Err(e) if !retry(&e) => break e,
+                _ => (),
+            }
+
+            for cqe in ring.completion() {
+                pending -= 1;
+                result = result.and(check(cqe.result()));
+            }
+        };
+
+        // Only wait for the operations the kernel has taken: the rest are
+        // never submitted, since the ring is dropped below.
+        let mut flight = pending - ring.submission().len();
+        while flight > 0 {
+            let flags = EnterFlags::GETEVENTS.bits();
+            match unsafe { ring.submitter().enter::<libc::sigset_t>(0, 1, flags, None) } {
+                Err(e) if !retry(&e) => {
+                    // Returning would let the kernel write to freed buffers.
+                    std::process::abort();
+                }
+                _ => (),
+            }
+
+            flight -= ring.completion().count();
+        }
+
+        self.ring = None;
+        Err(error)
+    }
+}
+
+// Whether a failed `io_uring_enter` can be repeated
+fn retry(error: &Error) -> bool {
+    matches!(error.raw_os_error(), Some(libc::EINTR | libc::EBUSY))
+}
+
+// Checks the result of an operation that transfers a whole block.
+fn transferred<const SIZE: usize>(result: i32, short: ErrorKind) -> Result<()> {
+    match result {
+        n if n < 0 => Err(Error::from_raw_os_error(-n)),
+        n if n as usize != SIZE => Err(short.into()),
+        _ => Ok(()),
+    }
+}
+
+impl<const SIZE: usize> Device<SIZE> for Uring<SIZE> {
+    type Error = Error;
+
+    #[inline]
+    fn len(&self) -> u64 {
+        self.file.size
+    }
+
+    #[inline]
+    fn get(&mut self, index: u64) -> Result<[u8; SIZE]> {
+        let mut block = [[0; SIZE]];
+        self.read_blocks(index, &mut block)?;
+        Ok(block[0])
+    }
+
+    #[inline]
+    fn set(&mut self, index: u64, block: &[u8; SIZE]) -> Result<()> {
+        self.write_blocks(index, std::slice::from_ref(block))
+    }
+
+    fn flush(&mut self) -> Result<()> {
+        if self.ring.is_none() {
+            return self.file.flush();
+        }
+
+        let fd = types::Fd(self.file.file.as_raw_fd());
+        let entry = opcode::Fsync::new(fd)
+            .flags(types::FsyncFlags::DATASYNC)
+            .build();
+
+        self.submit(std::iter::once(entry), |n| match n {
+            n if n < 0 => Err(Error::from_raw_os_error(-n)),
+            _ => Ok(()),
+        })
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn check(mut device: Uring<512>) {
+        let blocks: Vec<[u8; 512]> = (0..16).map(|i| [i as u8; 512]).collect();
+        device.write_blocks(4, &blocks).unwrap();
+        device.flush().unwrap();
+
+        let mut check = vec![[0u8; 512]; 18];
+        device.read_blocks(3, &mut check).unwrap();
+        assert_eq!(check[0], [0; 512]);
+        assert_eq!(check[1..17], blocks[..]);
+        assert_eq!(check[17], [0; 512]);
+
+        device.set(0, &[0xff; 512]).unwrap();
+        assert_eq!(device.get(0).unwrap(), [0xff; 512]);
+    }
+
+    #[test]
+    fn uring() {
+        let file = tempfile::tempfile().unwrap();
+        file.set_len(32 * 512).unwrap();
+
+        // Queue less than the number of blocks to test refilling the queue.
+        let device = Uring::<512>::new(file, 4).unwrap();
+        if !device.is_uring() {
+            eprintln!("io_uring is unavailable; skipping");
+            return;
+        }
+
+        assert_eq!(device.len(), 32);
+        check(device);
+    }
+
+    #[test]
+    fn broken() {
+        let file = tempfile::tempfile().unwrap();
+        file.set_len(32 * 512).unwrap();
+
+        let mut device = Uring::<512>::new(file, 4).unwrap();
+        if !device.is_uring() {
+            eprintln!("io_uring is unavailable; skipping");
+            return;
+        }
+
+        // Replace the ring's descriptor, so that io_uring_enter() fails.
+        let null = std::fs::File::open("/dev/null").unwrap();
+        let ring = device.ring.as_ref().unwrap().as_raw_fd();
+        assert!(unsafe { libc::dup2(null.as_raw_fd(), ring) } >= 0);
+
+        let blocks = vec![[0xaa; 512]; 8];
+        assert!(device.write_blocks(0, &blocks).is_err());
+
+        // The queued writes are discarded along with the ring.
+        assert!(!device.is_uring());
+        assert_eq!(device.get(3).unwrap(), [0; 512]);
+        check(device);
+    }
+
+    #[test]
+    fn fallback() {
+        let file = tempfile::tempfile().unwrap();
+        file.set_len(32 * 512).unwrap();
+
+        let mut device = Uring::<512>::new(file, 4).unwrap();
+        device.ring = None;
+        check(device);
+    }
+}
@@ -0,0 +1,106 @@
+//! Asynchronous partitions
+
+use std::ops::Range;
+use std::sync::Arc;
+
+use async_trait::async_trait;
+use quoin_device::AsyncDevice;
+use tokio::sync::Mutex;
+
+/// A partition of an [AsyncDevice], which may be shared with others
+///
+/// The table itself is read synchronously (e.g. with [crate::Disk]), and
+/// [crate::Partition::blocks] then gives the blocks of each partition.
+pub struct Partition<T: AsyncDevice<SIZE>, const SIZE: usize> {
+    device: Arc<Mutex<T>>,
+    blocks: Range<u64>,
+}
+
+impl<T: AsyncDevice<SIZE>, const SIZE: usize> Partition<T, SIZE> {
+    pub fn new(device: Arc<Mutex<T>>, blocks: Range<u64>) -> Self {
+        assert!(blocks.start <= blocks.end);
+        Self { device, blocks }
+    }
+
+    /// The blocks of the device occupied by the partition
+    #[inline]
+    pub fn blocks(&self) -> Range<u64> {
+        self.blocks.clone()
+    }
+}
+
+#[async_trait]
+impl<T: AsyncDevice<SIZE>, const SIZE: usize> AsyncDevice<SIZE> for Partition<T, SIZE> {
+    type Error = T::Error;
+
+    #[inline]
+    fn len(&self) -> u64 {
+        self.blocks.end - self.blocks.start
+    }
+
+    async fn read(
+        &mut self,
+        index: u64,
+        block: Box<[u8; SIZE]>,
+    ) -> Result<Box<[u8; SIZE]>, Self::Error> {
+        assert!(index < self.len());
+
+        let index = index + self.blocks.start;
+        self.device.lock().await.read(index, block).await
+    }
+
+    async fn write(
+        &mut self,
+        index: u64,
+        block: Box<[u8; SIZE]>,
+    ) -> Result<Box<[u8; SIZE]>, Self::Error> {
+        assert!(index < self.len());
+
+        let index = index + self.blocks.start;
+        self.device.lock().await.write(index, block).await
+    }
+
+    async fn flush(&mut self) -> Result<(), Self::Error> {
+        self.device.lock().await.flush().await
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    use crate::{Attributes, Disk, Kind};
+
+    use quoin_blocking::Blocking;
+    use quoin_memory::Memory;
+
+    #[tokio::test]
+    async fn partition() {
+        let mut memory: Memory<512, 128> = Memory::default();
+
+        let mut disk = Disk::format(&mut memory).unwrap();
+        let kind = Kind::LINUX_FILESYSTEM.guid;
+        disk.add(kind, 40..48, Attributes::empty(), "a").unwrap();
+        disk.add(kind, 48..56, Attributes::empty(), "b").unwrap();
+        let blocks: Vec<_> = disk.partitions().iter().map(|p| p.blocks()).collect();
+        drop(disk);
+
+        let device = Arc::new(Mutex::new(Blocking::new(memory)));
+        let mut a = Partition::new(device.clone(), blocks[0].clone());
+        let mut b = Partition::new(device.clone(), blocks[1].clone());
+        assert_eq!(a.len(), 8);
+
+        let (x, y) = tokio::join!(
+            a.write(7, Box::new([0xaa; 512])),
+            b.write(0, Box::new([0xbb; 512]))
+        );
+        x.unwrap();
+        y.unwrap();
+
+        let mut device = device.lock().await;
+        let block = device.read(47, Box::new([0; 512])).await.unwrap();
+        assert_eq!(*block, [0xaa; 512]);
+        let block = device.read(48, block).await.unwrap();
+        assert_eq!(*block, [0xbb; 512]);
+    }
+}
@@ -0,0 +1,132 @@
+use std::ops::{BitAnd, BitOr, Not};
+
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Serialize};
+
+/// The attribute flags of a partition
+///
+/// Bits 0-2 have the same meaning for every partition type. Bits 48-63 are
+/// reserved for use by the partition type; the well-known meanings of some
+/// of those are provided here as well.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct Attributes(u64);
+
+impl Attributes {
+    /// The platform requires this partition to function properly
+    pub const REQUIRED: Self = Self(1 << 0);
+
+    /// Firmware must not produce a block I/O protocol for this partition
+    pub const NO_BLOCK_IO: Self = Self(1 << 1);
+
+    /// Legacy BIOS firmware may boot from this partition
+    pub const LEGACY_BIOS_BOOTABLE: Self = Self(1 << 2);
+
+    /// The file system should be grown to fill the partition on first mount
+    /// (Discoverable Partitions Specification)
+    pub const GROWFS: Self = Self(1 << 59);
+
+    /// The partition should be mounted read-only (Discoverable Partitions
+    /// Specification and Microsoft basic data)
+    pub const READ_ONLY: Self = Self(1 << 60);
+
+    /// The partition is a shadow copy (Microsoft basic data)
+    pub const SHADOW_COPY: Self = Self(1 << 61);
+
+    /// The partition is hidden (Microsoft basic data)
+    pub const HIDDEN: Self = Self(1 << 62);
+
+    /// The partition should not be mounted automatically (Discoverable
+    /// Partitions Specification)
+    pub const NO_AUTO: Self = Self(1 << 63);
+
+    /// The partition should not be assigned a drive letter (Microsoft basic
+    /// data)
+    pub const NO_DRIVE_LETTER: Self = Self(1 << 63);
+
+    const TYPE_SHIFT: u32 = 48;
+
+    #[inline]
+    pub const fn empty() -> Self {
+        Self(0)
+    }
+
+    #[inline]
+    pub const fn from_bits(bits: u64) -> Self {
+        Self(bits)
+    }
+
+    #[inline]
+    pub const fn bits(self) -> u64 {
+        self.0
+    }
+
+    #[inline]
+    pub const fn contains(self, other: Self) -> bool {
+        self.0 & other.0 == other.0
+    }
+
+    #[inline]
+    pub fn insert(&mut self, other: Self) {
+        self.0 |= other.0;
+    }
+
+    #[inline]
+    pub fn remove(&mut self, other: Self) {
+        self.0 &= !other.0;
+    }
+
+    /// Returns the type-specific bits (48-63)
+    #[inline]
+    pub const fn type_specific(self) -> u16 {
+        (self.0 >> Self::TYPE_SHIFT) as u16
+    }
+
+    /// Replaces the type-specific bits (48-63)
+    #[inline]
+    pub fn set_type_specific(&mut self, bits: u16) {
+        self.0 &= !(u64::from(u16::MAX) << Self::TYPE_SHIFT);
+        self.0 |= u64::from(bits) << Self::TYPE_SHIFT;
+    }
+}
+
+impl From<u64> for Attributes {
+    #[inline]
+    fn from(value: u64) -> Self {
+        Self(value)
+    }
+}
+
+impl From<Attributes> for u64 {
+    #[inline]
+    fn from(value: Attributes) -> Self {
+        value.0
+    }
+}
+
+impl BitOr for Attributes {
+    type Output = Self;
+
+    #[inline]
+    fn bitor(self, rhs: Self) -> Self {
+        Self(self.0 | rhs.0)
+    }
+}
+
+impl BitAnd for Attributes {
+    type Output = Self;
+
+    #[inline]
+    fn bitand(self, rhs: Self) -> Self {
+        Self(self.0 & rhs.0)
+    }
+}
+
+impl Not for Attributes {
+    type Output = Self;
+
+    #[inline]
+    fn not(self) -> Self {
+        Self(!self.0)
+    }
+}
@@ -0,0 +1,84 @@
+use super::Error;
+
+/// A single problem found in one copy of the table
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Problem {
+    /// The header signature is missing
+    Missing,
+
+    /// The header revision is not supported
+    Revision([u8; 4]),
+
+    /// The header size is not supported
+    HeaderSize(u32),
+
+    /// The entry size is not supported
+    EntrySize(u32),
+
+    /// The reserved header field is not zero
+    Reserved(u32),
+
+    /// The header checksum does not match
+    HeaderCrc,
+
+    /// The header does not record its own location
+    ThisLba(u64),
+
+    /// The header points to an invalid location for the other copy
+    OtherLba(u64),
+
+    /// The usable range is empty or overlaps the table
+    Usable,
+
+    /// The entry array is outside the device or overlaps the usable range
+    EntryLba(u64),
+
+    /// The entry array checksum does not match
+    EntryCrc,
+
+    /// The backup is not in the last block (i.e. the device has grown)
+    Misplaced(u64),
+
+    /// The partition with this number ends before it starts
+    Inverted(u32),
+
+    /// The partition with this number is outside of the usable range
+    Outside(u32),
+
+    /// The partitions with these numbers overlap
+    Overlap(u32, u32),
+
+    /// The partitions with these numbers have the same GUID
+    Duplicate(u32, u32),
+}
+
+impl Problem {
+    pub(crate) fn error<T>(self) -> Error<T> {
+        match self {
+            Self::Missing | Self::HeaderCrc | Self::EntryCrc => Error::Corrupted,
+            Self::Revision(..) | Self::HeaderSize(..) => Error::Unsupported,
+            Self::EntrySize(..) | Self::Reserved(..) => Error::Unsupported,
+            Self::Duplicate(..) => Error::Conflict,
+            _ => Error::OutOfBounds,
+        }
+    }
+}
+
+/// A header field on which the two copies of the table disagree
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Field {
+    Revision,
+    Guid,
+    Usable,
+    EntryCount,
+    EntrySize,
+    EntryCrc,
+}
+
+/// A finding reported by [crate::Disk::check]
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Finding {
+    Primary(Problem),
+    Backup(Problem),
+    Mismatch(Field),
+}
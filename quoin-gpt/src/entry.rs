@@ -1,8 +1,9 @@
-use super::{Error, Range};
+use super::{guid, Attributes, Error, Problem, Range};
 
 use std::cmp::max;
 
 use quoin_codec::codec;
+use uuid::Uuid;
 
 use nbytes::bytes;
 
@@ -17,56 +18,119 @@ codec! {
     }
 }
 
+type Blocks = std::ops::Range<u64>;
+
 impl Entry {
     pub const EMPTY: [u8; 16] = [0; 16];
     pub const SIZE: usize = 128;
-}
 
-pub trait EntriesExt {
-    const MIN_SIZE: usize = bytes![16; KiB];
+    /// Creates an entry, failing if the blocks are empty or the name too long
+    pub fn new(
+        kind: Uuid,
+        guid: Uuid,
+        blocks: Blocks,
+        attr: Attributes,
+        name: &str,
+    ) -> Option<Self> {
+        if blocks.start >= blocks.end {
+            return None;
+        }
 
-    fn blocks(&self, size: usize) -> usize;
-    fn validate<T>(&self, urange: Range) -> Result<(), Error<T>>;
-}
+        // Serialize the name
+        let mut buff = [0u16; 36];
+        for (i, c) in name.encode_utf16().enumerate() {
+            *buff.get_mut(i)? = c;
+        }
 
-impl EntriesExt for [Entry] {
-    fn blocks(&self, size: usize) -> usize {
-        let mut entry_blocks = Self::MIN_SIZE / size;
+        Some(Self {
+            kind: guid::encode(kind),
+            guid: guid::encode(guid),
+            data: Range {
+                first: blocks.start,
+                last: blocks.end - 1,
+            },
+            attr: attr.bits(),
+            name: buff,
+        })
+    }
+
+    /// An unused slot in the entry array
+    pub fn empty() -> Self {
+        Self {
+            kind: Self::EMPTY,
+            guid: [0; 16],
+            data: Range { first: 0, last: 0 },
+            attr: 0,
+            name: [0; 36],
+        }
+    }
+
+    /// The minimum number of bytes reserved for each entry array
+    pub const MIN_ARRAY: usize = bytes![16; KiB];
+
+    /// The number of blocks reserved for an array of `count` entries
+    pub fn blocks(count: usize, size: usize) -> usize {
+        let mut entry_blocks = Self::MIN_ARRAY / size;
 
-        while entry_blocks * size < max(Self::MIN_SIZE, self.len() * Entry::SIZE) {
+        while entry_blocks * size < max(Self::MIN_ARRAY, count * Entry::SIZE) {
             entry_blocks += 1;
         }
 
         entry_blocks
     }
+}
+
+pub trait EntriesExt {
+    fn problems(&self, urange: Range) -> Vec<Problem>;
 
     fn validate<T>(&self, urange: Range) -> Result<(), Error<T>> {
-        for entry in self {
+        match self.problems(urange).first() {
+            Some(problem) => Err(problem.error()),
+            None => Ok(()),
+        }
+    }
+}
+
+// Partitions are numbered from one, by slot.
+fn number(slot: usize) -> u32 {
+    slot as u32 + 1
+}
+
+impl EntriesExt for [Entry] {
+    fn problems(&self, urange: Range) -> Vec<Problem> {
+        let mut problems = Vec::new();
+
+        for (i, entry) in self.iter().enumerate() {
+            if entry.kind == Entry::EMPTY {
+                continue;
+            }
+
             if entry.data.first > entry.data.last {
-                return Err(Error::OutOfBounds);
+                problems.push(Problem::Inverted(number(i)));
+                continue;
             }
 
             // Make sure entries don't claim blocks used by GPT
             if !urange.contains(entry.data) {
-                return Err(Error::OutOfBounds);
+                problems.push(Problem::Outside(number(i)));
             }
 
             // Make sure that entries don't overlap.
-            for e in self {
-                if core::ptr::eq(entry, e) {
+            for (j, e) in self.iter().enumerate().skip(i + 1) {
+                if e.kind == Entry::EMPTY {
                     continue;
                 }
 
-                if e.data.overlaps(entry.data) {
-                    return Err(Error::OutOfBounds);
+                if e.data.first <= e.data.last && e.data.overlaps(entry.data) {
+                    problems.push(Problem::Overlap(number(i), number(j)));
                 }
 
                 if e.guid == entry.guid {
-                    return Err(Error::Conflict);
+                    problems.push(Problem::Duplicate(number(i), number(j)));
                 }
             }
         }
 
-        Ok(())
+        problems
     }
 }
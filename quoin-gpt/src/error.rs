@@ -1,11 +1,29 @@
+use std::fmt::{Display, Formatter};
+
+use quoin_device::ErrorKind;
+
 #[derive(Copy, Clone, Debug, PartialEq)]
 pub enum Error<T> {
+    /// The underlying device failed
     Parent(T),
+
+    /// The partition table is damaged
     Corrupted,
+
+    /// The block size does not match the partition table
     Mismatch,
+
+    /// The partition table uses an unsupported format
     Unsupported,
+
+    /// A partition or table does not fit on the device
     OutOfBounds,
+
+    /// A partition overlaps or duplicates another
     Conflict,
+
+    /// The partition does not exist
+    NotFound,
 }
 
 impl<T> From<T> for Error<T> {
@@ -13,3 +31,36 @@ impl<T> From<T> for Error<T> {
         Self::Parent(value)
     }
 }
+
+impl<T> Display for Error<T> {
+    fn fmt(&self, f: &mut Formatter) -> std::fmt::Result {
+        f.write_str(match self {
+            Self::Parent(..) => "device error",
+            Self::Corrupted => "the partition table is corrupted",
+            Self::Mismatch => "the block size does not match the partition table",
+            Self::Unsupported => "the partition table is not supported",
+            Self::OutOfBounds => "out of bounds",
+            Self::Conflict => "the partition conflicts with another",
+            Self::NotFound => "no such partition",
+        })
+    }
+}
+
+impl<T: std::error::Error + 'static> std::error::Error for Error<T> {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Self::Parent(e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+impl<T: quoin_device::Error> quoin_device::Error for Error<T> {
+    fn kind(&self) -> ErrorKind {
+        match self {
+            Self::Parent(e) => e.kind(),
+            Self::Corrupted => ErrorKind::Integrity,
+            _ => ErrorKind::Other,
+        }
+    }
+}
@@ -0,0 +1,21 @@
+use uuid::Uuid;
+
+// GPT stores the first three fields of each GUID in little-endian order,
+// while `Uuid` keeps all of its bytes in big-endian order.
+#[inline]
+fn swap(mut bytes: [u8; 16]) -> [u8; 16] {
+    bytes[0..4].reverse();
+    bytes[4..6].reverse();
+    bytes[6..8].reverse();
+    bytes
+}
+
+#[inline]
+pub fn decode(bytes: [u8; 16]) -> Uuid {
+    Uuid::from_bytes(swap(bytes))
+}
+
+#[inline]
+pub fn encode(guid: Uuid) -> [u8; 16] {
+    swap(*guid.as_bytes())
+}
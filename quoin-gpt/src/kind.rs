@@ -0,0 +1,72 @@
+use uuid::Uuid;
+
+/// A well-known partition type
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct Kind {
+    pub name: &'static str,
+    pub guid: Uuid,
+}
+
+macro_rules! kinds {
+    ($($id:ident = $name:literal, $guid:literal;)+) => {
+        impl Kind {
+            $(
+                pub const $id: Kind = Kind {
+                    name: $name,
+                    guid: Uuid::from_u128($guid),
+                };
+            )+
+
+            /// All of the partition types known to this crate
+            pub const KNOWN: &'static [Kind] = &[$(Kind::$id),+];
+        }
+    };
+}
+
+kinds! {
+    EFI_SYSTEM = "EFI System", 0xC12A7328_F81F_11D2_BA4B_00A0C93EC93B;
+    BIOS_BOOT = "BIOS boot", 0x21686148_6449_6E6F_744E_656564454649;
+
+    MICROSOFT_RESERVED = "Microsoft reserved", 0xE3C9E316_0B5C_4DB8_817D_F92DF00215AE;
+    MICROSOFT_BASIC_DATA = "Microsoft basic data", 0xEBD0A0A2_B9E5_4433_87C0_68B6B72699C7;
+
+    LINUX_FILESYSTEM = "Linux filesystem", 0x0FC63DAF_8483_4772_8E79_3D69D8477DE4;
+    LINUX_SWAP = "Linux swap", 0x0657FD6D_A4AB_43C4_84E5_0933C84B4F4F;
+    LINUX_LVM = "Linux LVM", 0xE6D6D379_F507_44C2_A23C_238F2A3DF928;
+    LINUX_RAID = "Linux RAID", 0xA19D880F_05FC_4D3B_A006_743F0F84911E;
+    LINUX_RESERVED = "Linux reserved", 0x8DA63339_0007_60C0_C436_083AC8230908;
+
+    // The Discoverable Partitions Specification
+    LINUX_ROOT_X86 = "Linux root (x86)", 0x44479540_F297_41B2_9AF7_D131D5F0458A;
+    LINUX_ROOT_X86_64 = "Linux root (x86-64)", 0x4F68BCE3_E8CD_4DB1_96E7_FBCAF984B709;
+    LINUX_ROOT_ARM = "Linux root (ARM)", 0x69DAD710_2CE4_4E3C_B16C_21A1D49ABED3;
+    LINUX_ROOT_ARM64 = "Linux root (ARM-64)", 0xB921B045_1DF0_41C3_AF44_4C6F280D3FAE;
+    LINUX_USR_X86_64 = "Linux /usr (x86-64)", 0x8484680C_9521_48C6_9C11_B0720656F69E;
+    LINUX_USR_ARM64 = "Linux /usr (ARM-64)", 0xB0E01050_EE5F_4390_949A_9101B17104E9;
+    LINUX_HOME = "Linux home", 0x933AC7E1_2EB4_4F13_B844_0E14E2AEF915;
+    LINUX_SERVER_DATA = "Linux server data", 0x3B8F8425_20E0_4F3B_907F_1A25A76F98E8;
+    LINUX_VARIABLE_DATA = "Linux variable data", 0x4D21B016_B534_45C2_A9FB_5C16E091FD2D;
+    LINUX_TEMPORARY_DATA = "Linux temporary data", 0x7EC6F557_3BC5_4ACA_B293_16EF5DF639D1;
+    LINUX_EXTENDED_BOOT = "Linux extended boot", 0xBC13C2FF_59E6_4262_A352_B275FD6F7172;
+}
+
+impl Kind {
+    /// Finds a well-known partition type by its GUID
+    pub fn from_guid(guid: Uuid) -> Option<&'static Kind> {
+        Self::KNOWN.iter().find(|k| k.guid == guid)
+    }
+
+    /// Finds a well-known partition type by its name (ignoring case)
+    pub fn from_name(name: &str) -> Option<&'static Kind> {
+        Self::KNOWN
+            .iter()
+            .find(|k| k.name.eq_ignore_ascii_case(name))
+    }
+}
+
+impl From<Kind> for Uuid {
+    #[inline]
+    fn from(value: Kind) -> Self {
+        value.guid
+    }
+}
@@ -0,0 +1,372 @@
+use super::{Attributes, Kind};
+
+use std::fmt::{Display, Formatter};
+use std::str::FromStr;
+
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+
+/// A description of a partition table
+///
+/// This is displayed and parsed in the script format of `sfdisk --dump`.
+/// With the `serde` feature, it can also be (de)serialized, e.g. as JSON.
+/// Missing fields take their default values when applied to a device.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[cfg_attr(feature = "serde", serde(default))]
+pub struct Layout {
+    /// The disk GUID (`label-id`)
+    pub guid: Option<Uuid>,
+
+    /// The device path (`device`), used to name the partitions
+    pub device: Option<String>,
+
+    /// The first usable block (`first-lba`)
+    pub first: Option<u64>,
+
+    /// The last usable block (`last-lba`)
+    pub last: Option<u64>,
+
+    /// The number of entry slots (`table-length`)
+    pub slots: Option<u32>,
+
+    /// The block size (`sector-size`)
+    pub block: Option<usize>,
+
+    pub partitions: Vec<Slot>,
+}
+
+/// A description of one partition in a [Layout]
+#[derive(Clone, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct Slot {
+    /// The partition number (i.e. its slot in the table plus one)
+    pub number: u32,
+
+    /// The first block of the partition
+    pub start: u64,
+
+    /// The number of blocks in the partition
+    pub size: u64,
+
+    /// The partition type
+    pub kind: Uuid,
+
+    /// The unique partition GUID (random by default)
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub guid: Option<Uuid>,
+
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub name: String,
+
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub attributes: Attributes,
+}
+
+/// An error encountered while parsing a [Layout]
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct ParseError {
+    /// The line on which the error occurred (starting at 1)
+    pub line: usize,
+
+    /// A description of the error
+    pub reason: &'static str,
+}
+
+impl Display for ParseError {
+    fn fmt(&self, f: &mut Formatter) -> std::fmt::Result {
+        write!(f, "line {}: {}", self.line, self.reason)
+    }
+}
+
+impl std::error::Error for ParseError {}
+
+// The names sfdisk uses for the attribute bits with a common meaning
+const ATTRIBUTES: &[(Attributes, &str)] = &[
+    (Attributes::REQUIRED, "RequiredPartition"),
+    (Attributes::NO_BLOCK_IO, "NoBlockIOProtocol"),
+    (Attributes::LEGACY_BIOS_BOOTABLE, "LegacyBIOSBootable"),
+];
+
+fn attributes(attr: Attributes) -> String {
+    let mut words: Vec<String> = ATTRIBUTES
+        .iter()
+        .filter(|(a, _)| attr.contains(*a))
+        .map(|(_, name)| name.to_string())
+        .collect();
+
+    // All of the other bits are listed by number.
+    let bits: Vec<String> = (3..64)
+        .filter(|bit| attr.contains(Attributes::from_bits(1 << bit)))
+        .map(|bit| bit.to_string())
+        .collect();
+
+    if !bits.is_empty() {
+        words.push(format!("GUID:{}", bits.join(",")));
+    }
+
+    words.join(" ")
+}
+
+fn parse_attributes(value: &str) -> Option<Attributes> {
+    let mut attr = Attributes::empty();
+
+    for word in value.split_whitespace() {
+        match ATTRIBUTES.iter().find(|(_, name)| *name == word) {
+            Some((a, _)) => attr.insert(*a),
+            None => {
+                let bits = word.strip_prefix("GUID:").unwrap_or(word);
+                for bit in bits.split(',').filter(|b| !b.is_empty()) {
+                    let bit: u32 = bit.parse().ok()?;
+                    attr.insert(Attributes::from_bits(1u64.checked_shl(bit)?));
+                }
+            }
+        }
+    }
+
+    Some(attr)
+}
+
+impl Layout {
+    // Names partitions the way Linux names its block devices.
+    fn node(&self, number: u32) -> String {
+        match self.device {
+            None => number.to_string(),
+            Some(ref dev) if dev.ends_with(|c: char| c.is_ascii_digit()) => {
+                format!("{}p{}", dev, number)
+            }
+            Some(ref dev) => format!("{}{}", dev, number),
+        }
+    }
+}
+
+impl Display for Layout {
+    fn fmt(&self, f: &mut Formatter) -> std::fmt::Result {
+        writeln!(f, "label: gpt")?;
+        if let Some(guid) = self.guid {
+            writeln!(f, "label-id: {:X}", guid.to_hyphenated())?;
+        }
+        if let Some(ref device) = self.device {
+            writeln!(f, "device: {}", device)?;
+        }
+        writeln!(f, "unit: sectors")?;
+        if let Some(first) = self.first {
+            writeln!(f, "first-lba: {}", first)?;
+        }
+        if let Some(last) = self.last {
+            writeln!(f, "last-lba: {}", last)?;
+        }
+        if let Some(slots) = self.slots {
+            writeln!(f, "table-length: {}", slots)?;
+        }
+        if let Some(block) = self.block {
+            writeln!(f, "sector-size: {}", block)?;
+        }
+
+        writeln!(f)?;
+        for slot in &self.partitions {
+            write!(
+                f,
+                "{} : start={:>12}, size={:>12}, type={:X}",
+                self.node(slot.number),
+                slot.start,
+                slot.size,
+                slot.kind.to_hyphenated()
+            )?;
+
+            if let Some(guid) = slot.guid {
+                write!(f, ", uuid={:X}", guid.to_hyphenated())?;
+            }
+            if !slot.name.is_empty() {
+                write!(f, ", name=\"{}\"", escape(&slot.name))?;
+            }
+            if slot.attributes != Attributes::empty() {
+                write!(f, ", attrs=\"{}\"", attributes(slot.attributes))?;
+            }
+
+            writeln!(f)?;
+        }
+
+        Ok(())
+    }
+}
+
+// Escapes quotes and backslashes with a backslash, as sfdisk does.
+fn escape(value: &str) -> String {
+    value.replace('\\', "\\\\").replace('"', "\\\"")
+}
+
+fn unescape(value: &str) -> Option<String> {
+    let mut chars = value.chars();
+    let mut unescaped = String::with_capacity(value.len());
+
+    while let Some(c) = chars.next() {
+        match c {
+            '\\' => unescaped.push(chars.next()?),
+            '"' => return None,
+            c => unescaped.push(c),
+        }
+    }
+
+    Some(unescaped)
+}
+
+// Splits the fields of a partition line on commas outside of quotes.
+fn fields(line: &str) -> Option<Vec<(&str, String)>> {
+    let mut fields = Vec::new();
+    let mut quoted = false;
+    let mut escaped = false;
+    let mut start = 0;
+
+    for (i, c) in line.char_indices().chain(Some((line.len(), ','))) {
+        match c {
+            _ if escaped => escaped = false,
+            '\\' if quoted => escaped = true,
+            '"' => quoted = !quoted,
+            ',' if !quoted => {
+                let field = line[start..i].trim();
+                start = i + 1;
+                if field.is_empty() {
+                    continue;
+                }
+
+                let mut kv = field.splitn(2, '=');
+                let key = kv.next()?.trim();
+                let value = kv.next()?.trim();
+                let value = match value.strip_prefix('"') {
+                    Some(value) => unescape(value.strip_suffix('"')?)?,
+                    None => value.into(),
+                };
+
+                fields.push((key, value));
+            }
+            _ => (),
+        }
+    }
+
+    if quoted {
+        return None;
+    }
+
+    Some(fields)
+}
+
+impl FromStr for Layout {
+    type Err = ParseError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut layout = Layout::default();
+        let mut number = 0;
+
+        for (i, line) in s.lines().enumerate() {
+            let err = |reason| ParseError {
+                line: i + 1,
+                reason,
+            };
+
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+
+            // Header lines are `key: value` pairs, with keys like `label-id`.
+            let key = line.split(':').next().unwrap();
+            let header = key.chars().all(|c| c.is_ascii_alphabetic() || c == '-');
+            if !key.is_empty() && key.len() < line.len() && header {
+                let value = line[key.len() + 1..].trim();
+
+                match key {
+                    "label" if value == "gpt" => (),
+                    "label" => return Err(err("unsupported label")),
+                    "unit" if value == "sectors" => (),
+                    "unit" => return Err(err("unsupported unit")),
+                    "label-id" => {
+                        let guid = value.parse().map_err(|_| err("invalid label-id"))?;
+                        layout.guid = Some(guid);
+                    }
+                    "device" => layout.device = Some(value.into()),
+                    "first-lba" => {
+                        let first = value.parse().map_err(|_| err("invalid first-lba"))?;
+                        layout.first = Some(first);
+                    }
+                    "last-lba" => {
+                        let last = value.parse().map_err(|_| err("invalid last-lba"))?;
+                        layout.last = Some(last);
+                    }
+                    "table-length" => {
+                        let slots = value.parse().map_err(|_| err("invalid table-length"))?;
+                        layout.slots = Some(slots);
+                    }
+                    "sector-size" => {
+                        let block = value.parse().map_err(|_| err("invalid sector-size"))?;
+                        layout.block = Some(block);
+                    }
+                    "grain" => (),
+                    _ => return Err(err("unknown header")),
+                }
+
+                continue;
+            }
+
+            // Partition lines may start with the name of the partition,
+            // which ends with the partition number. A colon after the first
+            // field is instead part of a value, like `attrs="GUID:48"`.
+            let mut rest = line;
+            number += 1;
+            if let Some(colon) = line.find(':') {
+                let node = line[..colon].trim();
+                if !node.contains(|c: char| c.is_whitespace() || c == ',' || c == '"') {
+                    let digits = node.rsplit(|c: char| !c.is_ascii_digit()).next();
+                    number = match digits.map(str::parse) {
+                        Some(Ok(n)) => n,
+                        _ => return Err(err("invalid partition name")),
+                    };
+
+                    rest = &line[colon + 1..];
+                }
+            }
+
+            let mut start = None;
+            let mut size = None;
+            let mut slot = Slot {
+                number,
+                start: 0,
+                size: 0,
+                kind: Kind::LINUX_FILESYSTEM.guid,
+                guid: None,
+                name: String::new(),
+                attributes: Attributes::empty(),
+            };
+
+            for (key, value) in fields(rest).ok_or_else(|| err("invalid fields"))? {
+                match key {
+                    "start" => start = Some(value.parse().map_err(|_| err("invalid start"))?),
+                    "size" => size = Some(value.parse().map_err(|_| err("invalid size"))?),
+                    "type" => {
+                        slot.kind = match Kind::from_name(&value) {
+                            Some(kind) => kind.guid,
+                            None => value.parse().map_err(|_| err("invalid type"))?,
+                        };
+                    }
+                    "uuid" => {
+                        let guid = value.parse().map_err(|_| err("invalid uuid"))?;
+                        slot.guid = Some(guid);
+                    }
+                    "name" => slot.name = value,
+                    "attrs" => {
+                        slot.attributes =
+                            parse_attributes(&value).ok_or_else(|| err("invalid attrs"))?;
+                    }
+                    _ => return Err(err("unknown field")),
+                }
+            }
+
+            slot.start = start.ok_or_else(|| err("missing start"))?;
+            slot.size = size.ok_or_else(|| err("missing size"))?;
+            layout.partitions.push(slot);
+        }
+
+        Ok(layout)
+    }
+}
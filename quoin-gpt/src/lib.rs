@@ -1,29 +1,68 @@
+#[cfg(feature = "async")]
+pub mod aio;
+
+mod attr;
+mod check;
 mod entry;
 mod error;
+mod guid;
 mod header;
+mod kind;
+mod layout;
 mod range;
+mod shared;
 mod table;
 
+pub use attr::Attributes;
+pub use check::{Field, Finding, Problem};
 pub use error::Error;
+pub use kind::Kind;
+pub use layout::{Layout, ParseError, Slot};
+pub use shared::{Shared, State};
 
 use entry::{EntriesExt, Entry};
 use header::Header;
 use range::Range;
-use table::DeviceExt;
+use table::{DeviceExt, Placement};
 
+use std::cell::RefCell;
+use std::cmp::max;
+use std::marker::PhantomData;
 use std::rc::Rc;
+use std::sync::{Arc, Mutex};
 
+use nbytes::bytes;
+use quoin_codec::Codec;
 use quoin_device::Device;
 use uuid::Uuid;
 
-pub struct Disk<T: Device<SIZE>, const SIZE: usize> {
-    device: Rc<T>,
+/// A disk whose partitions can be used from a single thread
+pub type Disk<T, const SIZE: usize> = Gpt<T, SIZE, Rc<RefCell<State<T>>>>;
+
+/// A disk whose partitions can be sent to, and used from, other threads
+pub type SyncDisk<T, const SIZE: usize> = Gpt<T, SIZE, Arc<Mutex<State<T>>>>;
+
+/// A partition of a [SyncDisk]
+pub type SyncPartition<T, const SIZE: usize> = Partition<T, SIZE, Arc<Mutex<State<T>>>>;
+
+/// A GUID partition table on a device
+///
+/// GUIDs are stored with their first three fields in little-endian order, as
+/// the UEFI specification requires. Earlier versions of this crate stored
+/// them in big-endian order, so the disk, type and partition GUIDs of tables
+/// they wrote are read back with those fields byte-swapped.
+pub struct Gpt<T: Device<SIZE>, const SIZE: usize, S: Shared<State<T>>> {
+    device: S,
     guid: Uuid,
     usable: Range,
+    backup: u64,
+    elba: u64,
+    slots: u32,
     entries: Vec<Entry>,
+    phantom: PhantomData<fn() -> T>,
 }
 
-impl<T: Device<SIZE>, const SIZE: usize> std::fmt::Debug for Disk<T, SIZE> {
+impl<T: Device<SIZE>, const SIZE: usize, S: Shared<State<T>>> std::fmt::Debug for Gpt<T, SIZE, S> {
     fn fmt(&self, f: &mut std::fmt::Formatter) -> Result<(), std::fmt::Error> {
         f.debug_struct("Disk")
             .field("guid", &self.guid())
@@ -32,24 +71,37 @@ impl<T: Device<SIZE>, const SIZE: usize> std::fmt::Debug for Disk<T, SIZE> {
     }
 }
 
-impl<T: Device<SIZE>, const SIZE: usize> Disk<T, SIZE> {
+impl<T: Device<SIZE>, const SIZE: usize, S: Shared<State<T>>> Gpt<T, SIZE, S> {
     pub fn load(mut device: T) -> Result<Option<Self>, Error<T::Error>> {
         assert_eq!(SIZE % Entry::SIZE, 0);
 
-        let tail = device.len() - 1;
-        let tail = device.load(tail);
         let head = device.load(1);
 
+        // If the device has grown, the backup is no longer in the last block.
+        let backup = match head {
+            Ok(Some(ref head)) => head.header.other_lba,
+            _ => device.len() - 1,
+        };
+
+        let tail = device.load(backup);
+
+        // Without a primary table, the primary entries go in the usual place.
+        let elba = match head {
+            Ok(Some(ref head)) => head.header.elba,
+            _ => 2,
+        };
+
         let table = match (head, tail) {
             (Ok(None), Ok(None)) => return Ok(None),
 
+            // Two valid copies that disagree mean one of them is damaged.
             (Ok(Some(head)), Ok(Some(tail))) => {
                 if head.header.guid != tail.header.guid {
-                    return Err(Error::Conflict);
+                    return Err(Error::Corrupted);
                 }
 
                 if head.header.ecrc32 != tail.header.ecrc32 {
-                    return Err(Error::Conflict);
+                    return Err(Error::Corrupted);
                 }
 
                 head
@@ -62,45 +114,250 @@ impl<T: Device<SIZE>, const SIZE: usize> Disk<T, SIZE> {
             (.., Err(tail)) => return Err(tail),
         };
 
-        let device = Rc::new(device);
         Ok(Some(Self {
-            device,
-            guid: Uuid::from_bytes(table.header.guid),
+            device: S::new(State {
+                device,
+                entries: table.entries.clone(),
+            }),
+            guid: guid::decode(table.header.guid),
             usable: table.header.usable,
+            backup,
+            elba,
+            slots: table.header.ecount,
             entries: table.entries,
+            phantom: PhantomData,
         }))
     }
 
-    pub fn format(mut device: T) -> Result<Self, Error<T::Error>> {
+    /// Runs every validation on both copies of the table
+    ///
+    /// Unlike [Disk::load], this does not stop at the first problem. Instead,
+    /// it reports everything wrong with either copy, as well as any header
+    /// fields on which the two copies disagree.
+    pub fn check(mut device: T) -> Result<Vec<Finding>, T::Error> {
+        let last = device.len() - 1;
+        let head = device.scan(1)?;
+
+        // If the device has grown, the backup is no longer in the last block.
+        let backup = match head.table {
+            Some(ref table) if head.problems.is_empty() => table.header.other_lba,
+            _ => last,
+        };
+
+        let tail = device.scan(backup)?;
+
+        let mut findings: Vec<Finding> = head
+            .problems
+            .into_iter()
+            .map(Finding::Primary)
+            .chain(tail.problems.into_iter().map(Finding::Backup))
+            .collect();
+
+        if let (Some(head), Some(tail)) = (head.table, tail.table) {
+            if backup != last {
+                findings.push(Finding::Backup(Problem::Misplaced(backup)));
+            }
+
+            let (head, tail) = (head.header, tail.header);
+            let fields = [
+                (Field::Revision, head.revision == tail.revision),
+                (Field::Guid, head.guid == tail.guid),
+                (Field::Usable, head.usable == tail.usable),
+                (Field::EntryCount, head.ecount == tail.ecount),
+                (Field::EntrySize, head.esize == tail.esize),
+                (Field::EntryCrc, head.ecrc32 == tail.ecrc32),
+            ];
+
+            for (field, equal) in fields.iter() {
+                if !equal {
+                    findings.push(Finding::Mismatch(*field));
+                }
+            }
+        }
+
+        Ok(findings)
+    }
+
+    pub fn format(device: T) -> Result<Self, Error<T::Error>> {
+        Self::format_with(device, Options::default())
+    }
+
+    pub fn format_with(device: T, options: Options) -> Result<Self, Error<T::Error>> {
+        Self::create(device, options, &[])
+    }
+
+    /// Writes a new table described by `layout`
+    ///
+    /// The whole layout is validated before anything is written.
+    pub fn apply(device: T, layout: &Layout) -> Result<Self, Error<T::Error>> {
+        if layout.block.unwrap_or(SIZE) != SIZE {
+            return Err(Error::Mismatch);
+        }
+
+        // The entry arrays must fit before the usable range and on the
+        // device, which bounds the slots that a layout can name.
+        let room = match layout.first {
+            Some(first) => first.saturating_sub(2),
+            None => device.len().saturating_sub(3) / 2,
+        };
+        let capacity = room.saturating_mul((SIZE / Entry::SIZE) as u64);
+
+        let mut entries = Vec::new();
+        for slot in &layout.partitions {
+            let index = (slot.number as usize).checked_sub(1);
+            let index = index.ok_or(Error::OutOfBounds)?;
+            if index as u64 >= capacity {
+                return Err(Error::OutOfBounds);
+            }
+
+            if index >= entries.len() {
+                entries.resize(index + 1, Entry::empty());
+            }
+
+            if entries[index].kind != Entry::EMPTY {
+                return Err(Error::Conflict);
+            }
+
+            let end = slot.start.checked_add(slot.size);
+            entries[index] = Entry::new(
+                slot.kind,
+                slot.guid.unwrap_or_else(Uuid::new_v4),
+                slot.start..end.ok_or(Error::OutOfBounds)?,
+                slot.attributes,
+                &slot.name,
+            )
+            .ok_or(Error::OutOfBounds)?;
+        }
+
+        let options = Options {
+            guid: layout.guid,
+            slots: layout.slots,
+            first: layout.first,
+            last: layout.last,
+        };
+
+        Self::create(device, options, &entries)
+    }
+
+    fn create(mut device: T, options: Options, entries: &[Entry]) -> Result<Self, Error<T::Error>> {
         assert_eq!(SIZE % Entry::SIZE, 0);
 
-        let guid = Uuid::new_v4();
-        device.save(*guid.as_bytes(), None, &[])?;
-        Ok(Disk::load(device)?.unwrap())
+        let slots = options.slots.unwrap_or(Options::SLOTS);
+        let slots = max(slots, entries.len() as u32);
+        let eblocks = Entry::blocks(slots as usize, SIZE) as u64;
+        let last = device.len().checked_sub(2 + eblocks);
+
+        let placement = Placement {
+            guid: guid::encode(options.guid.unwrap_or_else(Uuid::new_v4)),
+            usable: Range {
+                first: options.first.unwrap_or(2 + eblocks),
+                last: options.last.or(last).ok_or(Error::OutOfBounds)?,
+            },
+            elba: 2,
+            slots,
+        };
+
+        device.save(&placement, entries)?;
+        Ok(Self::load(device)?.unwrap())
+    }
+
+    /// Describes the table, e.g. for export with `sfdisk`
+    pub fn layout(&self) -> Layout {
+        Layout {
+            guid: Some(self.guid),
+            device: None,
+            first: Some(self.usable.first),
+            last: Some(self.usable.last),
+            slots: Some(max(self.slots as usize, self.entries.len()) as u32),
+            block: Some(SIZE),
+            partitions: self
+                .partitions()
+                .iter()
+                .map(|p| Slot {
+                    number: p.number,
+                    start: p.blocks().start,
+                    size: p.len(),
+                    kind: p.kind(),
+                    guid: Some(p.guid()),
+                    name: p.name().unwrap_or_default(),
+                    attributes: p.attributes(),
+                })
+                .collect(),
+        }
     }
 
     pub fn guid(&self) -> Uuid {
         self.guid
     }
 
-    pub fn partitions(&self) -> Vec<Partition<T, SIZE>> {
+    pub fn partitions(&self) -> Vec<Partition<T, SIZE, S>> {
         self.entries
             .iter()
-            .cloned()
-            .map(|x| Partition {
+            .enumerate()
+            .filter(|(_, e)| e.kind != Entry::EMPTY)
+            .map(|(slot, e)| Partition {
                 device: self.device.clone(),
-                entry: x,
+                number: slot as u32 + 1,
+                guid: e.guid,
+                phantom: PhantomData,
             })
             .collect()
     }
 
+    /// Returns the partition with the given number
+    ///
+    /// Partitions are numbered from 1 by their slot in the entry array, just
+    /// as the operating system numbers them (e.g. `/dev/sda1`).
+    pub fn partition(&self, number: u32) -> Option<Partition<T, SIZE, S>> {
+        let slot = (number as usize).checked_sub(1)?;
+        let entry = self.entries.get(slot)?;
+        if entry.kind == Entry::EMPTY {
+            return None;
+        }
+
+        Some(Partition {
+            device: self.device.clone(),
+            number,
+            guid: entry.guid,
+            phantom: PhantomData,
+        })
+    }
+
+    /// Returns the partition with the given unique GUID
+    pub fn find_by_guid(&self, guid: Uuid) -> Option<Partition<T, SIZE, S>> {
+        self.partitions().into_iter().find(|p| p.guid() == guid)
+    }
+
+    /// Returns the lowest-numbered partition with the given name
+    pub fn find_by_label(&self, label: &str) -> Option<Partition<T, SIZE, S>> {
+        self.partitions()
+            .into_iter()
+            .find(|p| p.name().ok().as_deref() == Some(label))
+    }
+
+    /// Returns all of the partitions of the given type
+    pub fn find_by_type(&self, kind: Uuid) -> Vec<Partition<T, SIZE, S>> {
+        self.partitions()
+            .into_iter()
+            .filter(|p| p.kind() == kind)
+            .collect()
+    }
+
     pub fn holes(&self) -> Vec<std::ops::Range<u64>> {
         let mut current = self.usable.first..self.usable.last + 1;
         let mut holes = Vec::new();
 
-        for entry in &self.entries {
-            let next = current.start..entry.data.first;
-            current = entry.data.last + 1..current.end;
+        let mut used: Vec<Range> = self
+            .entries
+            .iter()
+            .filter(|e| e.kind != Entry::EMPTY)
+            .map(|e| e.data)
+            .collect();
+        used.sort_by_key(|r| r.first);
+
+        for data in used {
+            let next = current.start..data.first;
+            current = data.last + 1..current.end;
 
             if next.start != next.end {
                 holes.push(next);
@@ -114,60 +371,300 @@ impl<T: Device<SIZE>, const SIZE: usize> Disk<T, SIZE> {
         holes
     }
 
+    /// Finds a place for a new partition of at least `size` bytes
+    ///
+    /// The start of the partition is aligned as described by `alignment`,
+    /// rounded up to a multiple of the block size. The returned blocks can be
+    /// passed directly to [Gpt::add]. Returns `None` if there is no room or if
+    /// the size or alignment is zero.
+    pub fn allocate(
+        &self,
+        size: u64,
+        alignment: Alignment,
+        strategy: Strategy,
+    ) -> Option<std::ops::Range<u64>> {
+        let bytes = alignment.bytes.unwrap_or(Strategy::ALIGNMENT);
+        let physical = alignment.physical_block_size.unwrap_or(SIZE as u64);
+        let offset = alignment.alignment_offset.unwrap_or(0);
+        if bytes == 0 || physical == 0 {
+            return None;
+        }
+
+        let align = lcm(lcm(bytes, physical)?, SIZE as u64)? / SIZE as u64;
+        let offset = offset / SIZE as u64 % align;
+        let blocks = size.div_ceil(SIZE as u64);
+        if blocks == 0 {
+            return None;
+        }
+
+        // Aligned blocks are those a multiple of `align` past the offset.
+        let fits = self.holes().into_iter().filter_map(|hole| {
+            let first = hole.start.saturating_sub(offset).div_ceil(align) * align + offset;
+            if first.checked_add(blocks)? > hole.end {
+                return None;
+            }
+
+            let start = match strategy {
+                Strategy::AtEnd => (hole.end - blocks - offset) / align * align + offset,
+                _ => first,
+            };
+
+            Some((hole.end - hole.start, start..start + blocks))
+        });
+
+        let fit = match strategy {
+            Strategy::FirstFit => fits.min_by_key(|(_, blocks)| blocks.start),
+            Strategy::BestFit => fits.min_by_key(|(size, _)| *size),
+            Strategy::AtEnd => fits.max_by_key(|(_, blocks)| blocks.start),
+        };
+
+        fit.map(|(_, blocks)| blocks)
+    }
+
+    /// Adds a partition in the first empty slot, returning its number
     pub fn add(
         &mut self,
         kind: Uuid,
         blocks: std::ops::Range<u64>,
-        attr: u64,
+        attr: Attributes,
+        name: &str,
+    ) -> Result<u32, Error<T::Error>> {
+        // Use the first empty slot, so that other partitions keep their numbers.
+        let slot = self.entries.iter().position(|e| e.kind == Entry::EMPTY);
+        let number = slot.unwrap_or(self.entries.len()) as u32 + 1;
+
+        self.add_at(number, kind, blocks, attr, name)?;
+        Ok(number)
+    }
+
+    /// Adds a partition with the given number
+    ///
+    /// The entry array grows to include the slot if needed. Fails with
+    /// [Error::Conflict] if the slot is already in use.
+    pub fn add_at(
+        &mut self,
+        number: u32,
+        kind: Uuid,
+        blocks: std::ops::Range<u64>,
+        attr: Attributes,
         name: &str,
     ) -> Result<(), Error<T::Error>> {
-        // Serialize the name
-        let mut buff = [0u16; 36];
-        let mut i = 0;
-        for c in name.encode_utf16() {
-            if i > buff.len() {
-                return Err(Error::OutOfBounds);
-            }
+        let slot = (number as usize).checked_sub(1).ok_or(Error::OutOfBounds)?;
+        if slot >= self.capacity() {
+            return Err(Error::OutOfBounds);
+        }
 
-            buff[i] = c;
-            i += 1;
+        let entry = self.entries.get(slot);
+        if entry.filter(|e| e.kind != Entry::EMPTY).is_some() {
+            return Err(Error::Conflict);
         }
 
-        let entry = Entry {
-            kind: *kind.as_bytes(),
-            data: Range {
-                first: blocks.start,
-                last: blocks.end - 1,
-            },
-            guid: *Uuid::new_v4().as_bytes(),
-            attr,
-            name: buff,
-        };
+        let entry = Entry::new(kind, Uuid::new_v4(), blocks, attr, name);
+        let entry = entry.ok_or(Error::OutOfBounds)?;
 
-        self.entries.push(entry);
+        let previous = self.entries.clone();
+        if slot >= self.entries.len() {
+            self.entries.resize(slot + 1, Entry::empty());
+        }
+        self.entries[slot] = entry;
 
-        let result = Rc::get_mut(&mut self.device).unwrap().save(
-            *self.guid.as_bytes(),
-            Some(self.usable),
-            &self.entries[..],
-        );
+        let result = self.write(self.usable);
+        if result.is_err() {
+            self.entries = previous;
+        }
+
+        result
+    }
+
+    // The number of slots that fit between the primary entries and the
+    // usable range, which bounds how far the entry array can grow.
+    fn capacity(&self) -> usize {
+        let blocks = self.usable.first.saturating_sub(self.elba);
+        (blocks as usize).saturating_mul(SIZE / Entry::SIZE)
+    }
+
+    /// Removes a partition, leaving its slot empty
+    pub fn remove(&mut self, number: u32) -> Result<(), Error<T::Error>> {
+        self.modify(number, |entry| {
+            *entry = Entry::empty();
+            Ok(())
+        })
+    }
+
+    /// Changes the number of blocks in a partition without moving its start
+    pub fn resize(&mut self, number: u32, blocks: u64) -> Result<(), Error<T::Error>> {
+        self.modify(number, |entry| {
+            let last = blocks.checked_sub(1);
+            let last = last.and_then(|n| entry.data.first.checked_add(n));
+            entry.data.last = last.ok_or(Error::OutOfBounds)?;
+            Ok(())
+        })
+    }
+
+    // Changes one entry, keeping the previous one if the table can't be saved.
+    fn modify(
+        &mut self,
+        number: u32,
+        func: impl FnOnce(&mut Entry) -> Result<(), Error<T::Error>>,
+    ) -> Result<(), Error<T::Error>> {
+        let slot = (number as usize).checked_sub(1).ok_or(Error::NotFound)?;
+        let entry = self.entries.get_mut(slot).ok_or(Error::NotFound)?;
+        if entry.kind == Entry::EMPTY {
+            return Err(Error::NotFound);
+        }
+
+        let previous = entry.clone();
+        let result = match func(entry) {
+            Ok(()) => self.write(self.usable),
+            Err(e) => Err(e),
+        };
 
         if result.is_err() {
-            self.entries.pop();
+            self.entries[slot] = previous;
         }
 
         result
     }
+
+    /// Rewrites both copies of the table from the loaded one
+    ///
+    /// This restores a missing or corrupted copy. On a device that has grown,
+    /// the backup is also moved to the last block.
+    pub fn repair(&mut self) -> Result<(), Error<T::Error>> {
+        self.write(self.usable)
+    }
+
+    /// Moves the backup table to the end of a grown device
+    ///
+    /// The usable range is extended to cover all of the newly added blocks.
+    pub fn relocate_backup(&mut self) -> Result<(), Error<T::Error>> {
+        let slots = max(self.slots as usize, self.entries.len());
+        let eblocks = Entry::blocks(slots, SIZE) as u64;
+        let usable = Range {
+            first: self.usable.first,
+            last: self.device.with(|s| s.device.len()) - 2 - eblocks,
+        };
+
+        self.write(usable)?;
+        self.usable = usable;
+        Ok(())
+    }
+
+    fn write(&mut self, usable: Range) -> Result<(), Error<T::Error>> {
+        let placement = Placement {
+            guid: guid::encode(self.guid),
+            usable,
+            elba: self.elba,
+            slots: self.slots,
+        };
+
+        let entries = &self.entries[..];
+        let backup = &mut self.backup;
+
+        self.device.with(|state| {
+            let device = &mut state.device;
+            device.save(&placement, entries)?;
+
+            // Wipe the stale backup header left behind on a grown device,
+            // but never the primary or a block that holds something else.
+            let last = device.len() - 1;
+            if *backup != last && *backup != 1 {
+                let block = device.get(*backup)?;
+                let header = Header::decode(&block[..]).unwrap();
+                if header.signature == Header::SIGNATURE && header.this_lba == *backup {
+                    device.set(*backup, &[0; SIZE])?;
+                }
+            }
+
+            *backup = last;
+
+            // Only now that the table is written do partitions see it.
+            state.entries = entries.to_vec();
+            Ok(())
+        })
+    }
 }
 
-pub struct Partition<T: Device<N>, const N: usize> {
-    device: Rc<T>,
-    entry: Entry,
+/// Options for [Gpt::format_with]
+#[derive(Copy, Clone, Debug, Default)]
+pub struct Options {
+    /// The disk GUID (random by default)
+    pub guid: Option<Uuid>,
+
+    /// The number of entry slots (128 by default)
+    pub slots: Option<u32>,
+
+    /// The first usable block (just after the primary entries by default)
+    pub first: Option<u64>,
+
+    /// The last usable block (just before the backup entries by default)
+    pub last: Option<u64>,
 }
 
-impl<T: Device<SIZE>, const SIZE: usize> std::fmt::Debug for Partition<T, SIZE> {
+impl Options {
+    /// The default number of entry slots
+    pub const SLOTS: u32 = 128;
+}
+
+/// How [Gpt::allocate] chooses among the holes that can fit a partition
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Strategy {
+    /// Use the first hole, placing the partition at its start
+    FirstFit,
+
+    /// Use the smallest hole, placing the partition at its start
+    BestFit,
+
+    /// Use the last hole, placing the partition at its end
+    AtEnd,
+}
+
+impl Strategy {
+    /// The default partition alignment in bytes
+    pub const ALIGNMENT: u64 = bytes![1; MiB];
+}
+
+/// Where [Gpt::allocate] may start a partition
+///
+/// The physical block size and alignment offset usually come from the
+/// device's geometry, so that partitions never start part way through a
+/// physical block.
+#[derive(Copy, Clone, Debug, Default)]
+pub struct Alignment {
+    /// The alignment of the partition start in bytes (1 MiB by default)
+    pub bytes: Option<u64>,
+
+    /// The physical block size in bytes (the block size by default)
+    pub physical_block_size: Option<u64>,
+
+    /// The offset in bytes of the first physically aligned block (0 by default)
+    pub alignment_offset: Option<u64>,
+}
+
+fn lcm(a: u64, b: u64) -> Option<u64> {
+    let (mut x, mut y) = (a, b);
+    while y != 0 {
+        let t = x % y;
+        x = y;
+        y = t;
+    }
+
+    (a / x).checked_mul(b)
+}
+
+pub struct Partition<T: Device<N>, const N: usize, S: Shared<State<T>> = Rc<RefCell<State<T>>>> {
+    device: S,
+    number: u32,
+    guid: [u8; 16],
+    phantom: PhantomData<fn() -> T>,
+}
+
+impl<T: Device<SIZE>, const SIZE: usize, S: Shared<State<T>>> std::fmt::Debug
+    for Partition<T, SIZE, S>
+{
     fn fmt(&self, f: &mut std::fmt::Formatter) -> Result<(), std::fmt::Error> {
         f.debug_struct("Partition")
+            .field("number", &self.number)
             .field("kind", &self.kind())
             .field("guid", &self.guid())
             .field("name", &self.name().unwrap_or_else(|_| "".into()))
@@ -175,49 +672,86 @@ impl<T: Device<SIZE>, const SIZE: usize> std::fmt::Debug for Partition<T, SIZE>
     }
 }
 
-impl<T: Device<SIZE>, const SIZE: usize> Partition<T, SIZE> {
+impl<T: Device<SIZE>, const SIZE: usize, S: Shared<State<T>>> Partition<T, SIZE, S> {
+    /// The number of the partition (i.e. its slot in the table plus one)
+    #[inline]
+    pub fn number(&self) -> u32 {
+        self.number
+    }
+
+    // The entry as last written, or an empty one if the partition is gone.
+    fn entry(&self) -> Entry {
+        let (number, guid) = (self.number, self.guid);
+        let entry = self.device.with(|s| s.entry(number, guid).cloned());
+        entry.unwrap_or_else(Entry::empty)
+    }
+
     #[inline]
     pub fn kind(&self) -> Uuid {
-        Uuid::from_bytes(self.entry.kind)
+        guid::decode(self.entry().kind)
     }
 
     #[inline]
     pub fn guid(&self) -> Uuid {
-        Uuid::from_bytes(self.entry.guid)
+        guid::decode(self.guid)
+    }
+
+    #[inline]
+    pub fn attributes(&self) -> Attributes {
+        Attributes::from_bits(self.entry().attr)
+    }
+
+    /// The blocks of the disk occupied by the partition
+    ///
+    /// This follows the table as it changes, and is empty once the partition
+    /// has been removed.
+    #[inline]
+    pub fn blocks(&self) -> std::ops::Range<u64> {
+        let (number, guid) = (self.number, self.guid);
+        self.device.with(|s| s.blocks(number, guid))
     }
 
     #[inline]
     pub fn name(&self) -> Result<String, std::string::FromUtf16Error> {
-        let len = self.entry.name.len();
-        let len = self.entry.name.iter().position(|&x| x == 0).unwrap_or(len);
-        String::from_utf16(&self.entry.name[..len])
+        let entry = self.entry();
+        let len = entry.name.len();
+        let len = entry.name.iter().position(|&x| x == 0).unwrap_or(len);
+        String::from_utf16(&entry.name[..len])
     }
 }
 
-impl<T: Device<SIZE>, const SIZE: usize> Device<SIZE> for Partition<T, SIZE> {
-    type Error = T::Error;
+impl<T: Device<SIZE>, const SIZE: usize, S: Shared<State<T>>> Device<SIZE>
+    for Partition<T, SIZE, S>
+{
+    type Error = Error<T::Error>;
 
     #[inline]
     fn len(&self) -> u64 {
-        self.entry.data.last - self.entry.data.first + 1
+        let blocks = self.blocks();
+        blocks.end - blocks.start
     }
 
     #[inline]
     fn get(&mut self, index: u64) -> Result<[u8; SIZE], Self::Error> {
-        assert!(index < self.len());
-
-        Rc::get_mut(&mut self.device)
-            .unwrap()
-            .get(index + self.entry.data.first)
+        let (number, guid) = (self.number, self.guid);
+        self.device.with(|s| {
+            let block = s.block(number, guid, index).ok_or(Error::OutOfBounds)?;
+            Ok(s.device.get(block)?)
+        })
     }
 
     #[inline]
     fn set(&mut self, index: u64, blocks: &[u8; SIZE]) -> Result<(), Self::Error> {
-        assert!(index < self.len());
+        let (number, guid) = (self.number, self.guid);
+        self.device.with(|s| {
+            let block = s.block(number, guid, index).ok_or(Error::OutOfBounds)?;
+            Ok(s.device.set(block, blocks)?)
+        })
+    }
 
-        Rc::get_mut(&mut self.device)
-            .unwrap()
-            .set(index + self.entry.data.first, blocks)
+    #[inline]
+    fn flush(&mut self) -> Result<(), Self::Error> {
+        Ok(self.device.with(|s| s.device.flush())?)
     }
 }
 
@@ -225,7 +759,24 @@ impl<T: Device<SIZE>, const SIZE: usize> Device<SIZE> for Partition<T, SIZE> {
 mod test {
     use super::*;
 
-    use quoin_memory::Memory;
+    use quoin_device::{Error as _, ErrorKind};
+    use quoin_fault::{Fault, Operation, Plan};
+    use quoin_memory::{Memory, Sparse};
+
+    use crc::crc32::checksum_ieee as crc32;
+
+    // Rewrites a header, updating its checksum to cover `size` bytes.
+    fn rewrite(device: &mut Memory<512, 128>, index: u64, func: impl FnOnce(&mut Header)) {
+        let mut block = device.get(index).unwrap();
+        let mut header = Header::decode(&block[..]).unwrap();
+        func(&mut header);
+
+        header.crc32 = 0;
+        header.encode(&mut block[..]).unwrap();
+        header.crc32 = crc32(&block[..header.size as usize]);
+        header.encode(&mut block[..]).unwrap();
+        device.set(index, &block).unwrap();
+    }
 
     #[test]
     fn empty() {
@@ -254,8 +805,13 @@ mod test {
         assert_eq!(disk.holes().len(), 1);
         assert_eq!(disk.partitions().len(), 0);
 
-        disk.add(kind, disk.holes().pop().unwrap(), 0, "foo")
-            .unwrap();
+        disk.add(
+            kind,
+            disk.holes().pop().unwrap(),
+            Attributes::empty(),
+            "foo",
+        )
+        .unwrap();
         assert_eq!(disk.holes().len(), 0);
         assert_eq!(disk.partitions().len(), 1);
 
@@ -268,6 +824,725 @@ mod test {
         assert_eq!(part.kind(), kind);
     }
 
+    #[test]
+    fn format_with() {
+        let mut device: Memory<512, 256> = Memory::default();
+        let guid = Uuid::new_v4();
+
+        let options = Options {
+            guid: Some(guid),
+            slots: Some(256),
+            first: Some(128),
+            last: None,
+        };
+
+        let disk = Disk::format_with(&mut device, options).unwrap();
+        assert_eq!(disk.guid(), guid);
+        assert_eq!(disk.holes(), vec![128..191]);
+
+        let disk = Disk::load(&mut device).unwrap().unwrap();
+        assert_eq!(disk.guid(), guid);
+        assert_eq!(disk.holes(), vec![128..191]);
+
+        let table = device.load(1).unwrap().unwrap();
+        assert_eq!(table.header.ecount, 256);
+
+        let options = Options {
+            first: Some(33),
+            ..Options::default()
+        };
+
+        let result = Disk::format_with(&mut device, options);
+        assert_eq!(result.unwrap_err(), Error::OutOfBounds);
+    }
+
+    #[test]
+    fn elba() {
+        let mut device: Memory<512, 128> = Memory::default();
+        let kind = Uuid::new_v4();
+
+        // Place the primary entries somewhere other than block 2.
+        let placement = Placement {
+            guid: guid::encode(Uuid::new_v4()),
+            usable: Range {
+                first: 64,
+                last: 94,
+            },
+            elba: 10,
+            slots: 128,
+        };
+        device.save(&placement, &[]).unwrap();
+        assert_eq!(Disk::check(&mut device).unwrap(), vec![]);
+
+        let mut disk = Disk::load(&mut device).unwrap().unwrap();
+        assert_eq!(disk.holes(), vec![64..95]);
+        disk.add(kind, 64..95, Attributes::empty(), "foo").unwrap();
+
+        let table = device.load(1).unwrap().unwrap();
+        assert_eq!(table.header.elba, 10);
+        assert_eq!(table.entries[0].kind, guid::encode(kind));
+
+        // The entries must not overlap the usable range.
+        let placement = Placement {
+            elba: 40,
+            ..placement
+        };
+        assert_eq!(
+            device.save(&placement, &[]).unwrap_err(),
+            Error::OutOfBounds
+        );
+    }
+
+    #[test]
+    fn header_size() {
+        let mut device: Memory<512, 128> = Memory::default();
+        let kind = Uuid::new_v4();
+
+        let mut disk = Disk::format(&mut device).unwrap();
+        disk.add(kind, 34..64, Attributes::empty(), "foo").unwrap();
+
+        for index in [1, 127].iter() {
+            rewrite(&mut device, *index, |h| h.size = 96);
+        }
+
+        assert_eq!(Disk::check(&mut device).unwrap(), vec![]);
+        let disk = Disk::load(&mut device).unwrap().unwrap();
+        assert_eq!(disk.partitions().len(), 1);
+
+        rewrite(&mut device, 1, |h| h.size = 88);
+        assert_eq!(device.load(1).unwrap_err(), Error::Unsupported);
+    }
+
+    #[test]
+    fn entry_size() {
+        let mut device: Memory<512, 128> = Memory::default();
+        let kind = Uuid::new_v4();
+
+        let mut disk = Disk::format(&mut device).unwrap();
+        disk.add(kind, 34..64, Attributes::empty(), "foo").unwrap();
+
+        // Rewrite the entries as 64 slots of 256 bytes each.
+        let mut old = Vec::new();
+        for i in 0..32 {
+            old.extend_from_slice(&device.get(2 + i).unwrap());
+        }
+
+        let mut new = vec![0xaa; 64 * 256];
+        for i in 0..64 {
+            new[i * 256..][..128].copy_from_slice(&old[i * 128..][..128]);
+        }
+
+        for (i, block) in new.chunks(512).enumerate() {
+            let mut buf = [0u8; 512];
+            buf.copy_from_slice(block);
+            device.set(2 + i as u64, &buf).unwrap();
+            device.set(95 + i as u64, &buf).unwrap();
+        }
+
+        let ecrc32 = crc32(&new);
+        for index in [1, 127].iter() {
+            rewrite(&mut device, *index, |h| {
+                h.ecount = 64;
+                h.esize = 256;
+                h.ecrc32 = ecrc32;
+            });
+        }
+
+        let mut disk = Disk::load(&mut device).unwrap().unwrap();
+        assert_eq!(disk.partitions().pop().unwrap().name().unwrap(), "foo");
+
+        disk.add(kind, 64..95, Attributes::empty(), "bar").unwrap();
+        let disk = Disk::load(&mut device).unwrap().unwrap();
+        assert_eq!(disk.partitions().len(), 2);
+
+        let table = device.load(1).unwrap().unwrap();
+        assert_eq!(table.header.esize, 128);
+        assert_eq!(table.header.ecount, 64);
+    }
+
+    #[test]
+    fn slots() {
+        let mut device: Memory<512, 128> = Memory::default();
+        let empty = Entry::empty();
+        let kind = Uuid::new_v4();
+
+        let foo = Entry {
+            kind: guid::encode(kind),
+            guid: guid::encode(Uuid::new_v4()),
+            data: Range {
+                first: 34,
+                last: 63,
+            },
+            attr: 0,
+            name: [0; 36],
+        };
+
+        let placement = Placement {
+            guid: guid::encode(Uuid::new_v4()),
+            usable: Range {
+                first: 34,
+                last: 94,
+            },
+            elba: 2,
+            slots: 128,
+        };
+
+        // Another tool left the first two slots empty.
+        device
+            .save(&placement, &[empty.clone(), empty, foo])
+            .unwrap();
+
+        let mut disk = Disk::load(&mut device).unwrap().unwrap();
+        disk.add(kind, 64..95, Attributes::empty(), "bar").unwrap();
+
+        let table = device.load(1).unwrap().unwrap();
+        assert_eq!(table.entries.len(), 128);
+        assert_eq!(table.entries[0].data.first, 64);
+        assert_eq!(table.entries[1].kind, Entry::EMPTY);
+        assert_eq!(table.entries[2].data.first, 34);
+    }
+
+    #[test]
+    fn problem_numbers() {
+        let usable = Range {
+            first: 34,
+            last: 94,
+        };
+
+        let entry = |first, last| Entry {
+            kind: guid::encode(Uuid::new_v4()),
+            guid: guid::encode(Uuid::new_v4()),
+            data: Range { first, last },
+            attr: 0,
+            name: [0; 36],
+        };
+
+        // Problems name partitions by number, not by slot.
+        let entries = [Entry::empty(), entry(34, 63), entry(60, 70), entry(80, 75)];
+        assert_eq!(
+            entries.problems(usable),
+            vec![Problem::Overlap(2, 3), Problem::Inverted(4)]
+        );
+    }
+
+    #[test]
+    fn numbers() {
+        let mut device: Memory<512, 128> = Memory::default();
+        let kind = Uuid::new_v4();
+        let attr = Attributes::empty();
+
+        let mut disk = Disk::format(&mut device).unwrap();
+        assert_eq!(disk.add(kind, 34..40, attr, "foo").unwrap(), 1);
+        disk.add_at(4, kind, 40..50, attr, "bar").unwrap();
+        assert_eq!(
+            disk.add(Kind::LINUX_SWAP.guid, 50..60, attr, "baz")
+                .unwrap(),
+            2
+        );
+
+        let err = disk.add_at(4, kind, 60..70, attr, "qux").unwrap_err();
+        assert_eq!(err, Error::Conflict);
+        let err = disk.add_at(0, kind, 60..70, attr, "qux").unwrap_err();
+        assert_eq!(err, Error::OutOfBounds);
+
+        // There is no room to grow the entry array.
+        let err = disk.add_at(129, kind, 60..70, attr, "qux").unwrap_err();
+        assert_eq!(err, Error::OutOfBounds);
+        let err = disk
+            .add_at(u32::MAX, kind, 60..70, attr, "qux")
+            .unwrap_err();
+        assert_eq!(err, Error::OutOfBounds);
+
+        let disk = Disk::load(&mut device).unwrap().unwrap();
+        let numbers: Vec<u32> = disk.partitions().iter().map(|p| p.number()).collect();
+        assert_eq!(numbers, vec![1, 2, 4]);
+
+        let bar = disk.partition(4).unwrap();
+        assert_eq!(bar.name().unwrap(), "bar");
+        assert!(disk.partition(0).is_none());
+        assert!(disk.partition(3).is_none());
+        assert!(disk.partition(129).is_none());
+
+        assert_eq!(disk.find_by_guid(bar.guid()).unwrap().number(), 4);
+        assert_eq!(disk.find_by_label("baz").unwrap().number(), 2);
+        assert!(disk.find_by_label("qux").is_none());
+        assert_eq!(disk.find_by_type(kind).len(), 2);
+    }
+
+    #[test]
+    fn layout() {
+        let mut device: Memory<512, 128> = Memory::default();
+        let attr = Attributes::REQUIRED | Attributes::NO_AUTO;
+
+        let mut disk = Disk::format(&mut device).unwrap();
+        disk.add(Kind::EFI_SYSTEM.guid, 34..40, attr, "esp, \"boot\"")
+            .unwrap();
+        disk.add_at(3, Kind::LINUX_HOME.guid, 40..95, Attributes::empty(), "")
+            .unwrap();
+
+        let mut layout = disk.layout();
+        assert_eq!(layout.first, Some(34));
+        assert_eq!(layout.last, Some(94));
+        assert_eq!(layout.partitions.len(), 2);
+        assert_eq!(layout.partitions[1].number, 3);
+        assert_eq!(layout.to_string().parse(), Ok(layout.clone()));
+
+        layout.device = Some("/dev/nvme0n1".into());
+        let script = layout.to_string();
+        assert!(script.contains("/dev/nvme0n1p3 : start=          40, size=          55"));
+        assert!(script.contains("attrs=\"RequiredPartition GUID:63\""));
+        assert_eq!(script.parse(), Ok(layout.clone()));
+
+        // Quotes and backslashes in names are escaped.
+        let mut odd = layout.clone();
+        odd.partitions[0].name = "a\"b, c\\".into();
+        odd.device = Some("/dev/disk/by-path/x=y".into());
+        let script = odd.to_string();
+        assert!(script.contains("name=\"a\\\"b, c\\\\\""));
+        assert!(script.contains("/dev/disk/by-path/x=y1 : start="));
+        assert_eq!(script.parse(), Ok(odd));
+
+        let mut other: Memory<512, 128> = Memory::default();
+        let disk = Disk::apply(&mut other, &layout).unwrap();
+        layout.device = None;
+        assert_eq!(disk.layout(), layout);
+        assert_eq!(disk.partition(3).unwrap().kind(), Kind::LINUX_HOME.guid);
+
+        // Layouts are validated before anything is written.
+        let mut other: Memory<512, 128> = Memory::default();
+        layout.partitions[1].start = 39;
+        assert_eq!(
+            Disk::apply(&mut other, &layout).unwrap_err(),
+            Error::OutOfBounds
+        );
+        layout.partitions[1].start = 40;
+        layout.block = Some(4096);
+        assert_eq!(
+            Disk::apply(&mut other, &layout).unwrap_err(),
+            Error::Mismatch
+        );
+        assert!(Disk::load(&mut other).unwrap().is_none());
+    }
+
+    #[test]
+    fn sfdisk() {
+        let script = "\
+label: gpt
+label-id: 5C5B6A13-0E6F-4B2B-8F4A-6A1D8F5C7E21
+device: /dev/sda
+unit: sectors
+first-lba: 34
+last-lba: 94
+sector-size: 512
+
+/dev/sda1 : start=34, size=30, type=C12A7328-F81F-11D2-BA4B-00A0C93EC93B, name=\"EFI\"
+/dev/sda4 : start=64, size=31, type=Linux swap, attrs=\"LegacyBIOSBootable GUID:48,60\"
+";
+
+        let layout: Layout = script.parse().unwrap();
+        assert_eq!(layout.slots, None);
+        assert_eq!(layout.partitions[1].number, 4);
+        assert_eq!(layout.partitions[1].kind, Kind::LINUX_SWAP.guid);
+        assert_eq!(
+            layout.partitions[1].attributes,
+            Attributes::LEGACY_BIOS_BOOTABLE
+                | Attributes::READ_ONLY
+                | Attributes::from_bits(1 << 48)
+        );
+
+        let mut device: Memory<512, 128> = Memory::default();
+        let disk = Disk::apply(&mut device, &layout).unwrap();
+        assert_eq!(disk.guid(), layout.guid.unwrap());
+        assert_eq!(disk.find_by_label("EFI").unwrap().number(), 1);
+
+        let script = "/dev/sda4294967295 : start=34, size=30, type=Linux swap\n";
+        let layout: Layout = script.parse().unwrap();
+        let err = Disk::apply(&mut device, &layout).unwrap_err();
+        assert_eq!(err, Error::OutOfBounds);
+
+        let err = "label: dos\n".parse::<Layout>().unwrap_err();
+        assert_eq!(
+            err,
+            ParseError {
+                line: 1,
+                reason: "unsupported label"
+            }
+        );
+        let err = "1 : start=34, name=\"a\"b\"\n"
+            .parse::<Layout>()
+            .unwrap_err();
+        assert_eq!(err.reason, "invalid fields");
+        let err = "\n1 : start=34\n".parse::<Layout>().unwrap_err();
+        assert_eq!(
+            err,
+            ParseError {
+                line: 2,
+                reason: "missing size"
+            }
+        );
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn json() {
+        let mut device: Memory<512, 128> = Memory::default();
+        let mut disk = Disk::format(&mut device).unwrap();
+        disk.add(Kind::LINUX_SWAP.guid, 34..64, Attributes::empty(), "swap")
+            .unwrap();
+
+        let layout = disk.layout();
+        let json = serde_json::to_string(&layout).unwrap();
+        assert_eq!(serde_json::from_str::<Layout>(&json).unwrap(), layout);
+
+        let json = r#"{"partitions": [{"number": 2, "start": 40, "size": 8,
+            "kind": "0657fd6d-a4ab-43c4-84e5-0933c84b4f4f"}]}"#;
+        let layout: Layout = serde_json::from_str(json).unwrap();
+        let mut device: Memory<512, 128> = Memory::default();
+        let disk = Disk::apply(&mut device, &layout).unwrap();
+        assert_eq!(disk.partition(2).unwrap().kind(), Kind::LINUX_SWAP.guid);
+    }
+
+    #[test]
+    fn remove_resize() {
+        let mut device: Memory<512, 128> = Memory::default();
+        let kind = Uuid::new_v4();
+        let attr = Attributes::empty();
+
+        let mut disk = Disk::format(&mut device).unwrap();
+        disk.add(kind, 34..40, attr, "foo").unwrap();
+        disk.add(kind, 40..50, attr, "bar").unwrap();
+        disk.add(kind, 50..60, attr, "baz").unwrap();
+
+        disk.remove(2).unwrap();
+        assert_eq!(disk.remove(2).unwrap_err(), Error::NotFound);
+        assert_eq!(disk.remove(0).unwrap_err(), Error::NotFound);
+        assert_eq!(disk.holes(), vec![40..50, 60..95]);
+
+        assert_eq!(disk.resize(1, 17).unwrap_err(), Error::OutOfBounds);
+        assert_eq!(disk.resize(1, 0).unwrap_err(), Error::OutOfBounds);
+        assert_eq!(disk.resize(1, u64::MAX).unwrap_err(), Error::OutOfBounds);
+        disk.resize(1, 16).unwrap();
+        disk.resize(3, 5).unwrap();
+        assert_eq!(disk.resize(2, 5).unwrap_err(), Error::NotFound);
+
+        let disk = Disk::load(&mut device).unwrap().unwrap();
+        assert_eq!(disk.partition(1).unwrap().blocks(), 34..50);
+        assert!(disk.partition(2).is_none());
+        assert_eq!(disk.partition(3).unwrap().blocks(), 50..55);
+        assert_eq!(disk.holes(), vec![55..95]);
+    }
+
+    #[test]
+    fn holes() {
+        let mut device: Memory<512, 128> = Memory::default();
+        let kind = Uuid::new_v4();
+
+        let mut disk = Disk::format(&mut device).unwrap();
+        disk.add(kind, 80..90, Attributes::empty(), "foo").unwrap();
+        disk.add(kind, 40..50, Attributes::empty(), "bar").unwrap();
+        assert_eq!(disk.holes(), vec![34..40, 50..80, 90..95]);
+    }
+
+    #[test]
+    fn add_failure() {
+        let mut device: Memory<512, 128> = Memory::default();
+        let plan = Plan::new(|_| std::io::Error::from(std::io::ErrorKind::TimedOut));
+        let kind = Uuid::new_v4();
+
+        let mut disk = Disk::format(Fault::new(&mut device, plan.clone())).unwrap();
+        disk.add(kind, 40..50, Attributes::empty(), "foo").unwrap();
+
+        // The table is unchanged if it cannot be written.
+        plan.fail_at(Operation::Set(1));
+        let err = disk
+            .add(kind, 60..70, Attributes::empty(), "bar")
+            .unwrap_err();
+        assert!(matches!(
+            err,
+            Error::Parent(quoin_fault::Error::Injected(..))
+        ));
+        assert_eq!(err.kind(), ErrorKind::Io);
+        assert_eq!(disk.partitions().len(), 1);
+        assert_eq!(disk.holes(), vec![34..40, 50..95]);
+
+        let err = disk.remove(1).unwrap_err();
+        assert!(matches!(err, Error::Parent(..)));
+        assert_eq!(disk.partitions().len(), 1);
+
+        plan.clear();
+        assert_eq!(
+            disk.add(kind, 60..70, Attributes::empty(), "bar").unwrap(),
+            2
+        );
+        drop(disk);
+
+        let disk = Disk::load(&mut device).unwrap().unwrap();
+        let names: Vec<_> = disk
+            .partitions()
+            .iter()
+            .map(|p| p.name().unwrap())
+            .collect();
+        assert_eq!(names, ["foo", "bar"]);
+    }
+
+    #[test]
+    fn allocate() {
+        let mut device: Memory<512, 128> = Memory::default();
+        let kind = Uuid::new_v4();
+        let align = Alignment {
+            bytes: Some(bytes![4; KiB]),
+            ..Default::default()
+        };
+
+        let mut disk = Disk::format(&mut device).unwrap();
+        let none = Alignment::default();
+        assert_eq!(disk.allocate(1, none, Strategy::FirstFit), None);
+        assert_eq!(disk.allocate(0, align, Strategy::FirstFit), None);
+
+        let zero = Alignment {
+            bytes: Some(0),
+            ..Default::default()
+        };
+        assert_eq!(disk.allocate(1, zero, Strategy::FirstFit), None);
+
+        disk.add(kind, 66..70, Attributes::empty(), "foo").unwrap();
+        assert_eq!(disk.holes(), vec![34..66, 70..95]);
+
+        let size = bytes![4; KiB];
+        assert_eq!(disk.allocate(size, align, Strategy::FirstFit), Some(40..48));
+        assert_eq!(disk.allocate(size, align, Strategy::BestFit), Some(72..80));
+        assert_eq!(disk.allocate(size, align, Strategy::AtEnd), Some(80..88));
+
+        let size = bytes![12; KiB];
+        assert_eq!(disk.allocate(size, align, Strategy::FirstFit), Some(40..64));
+        assert_eq!(disk.allocate(size, align, Strategy::BestFit), Some(40..64));
+        assert_eq!(disk.allocate(size, align, Strategy::AtEnd), Some(40..64));
+
+        let size = bytes![1; KiB] + 1;
+        let blocks = disk.allocate(size, align, Strategy::FirstFit).unwrap();
+        assert_eq!(blocks, 40..43);
+        disk.add(kind, blocks, Attributes::empty(), "bar").unwrap();
+        assert_eq!(disk.holes(), vec![34..40, 43..66, 70..95]);
+    }
+
+    #[test]
+    fn allocate_4kn() {
+        let mut device: Memory<4096, 32> = Memory::default();
+
+        let disk = Disk::format(&mut device).unwrap();
+        assert_eq!(disk.holes(), vec![6..27]);
+
+        let size = bytes![4; KiB];
+        let align = |bytes| Alignment {
+            bytes: Some(bytes),
+            ..Default::default()
+        };
+
+        let found = disk.allocate(size, align(512), Strategy::FirstFit);
+        assert_eq!(found, Some(6..7));
+
+        let found = disk.allocate(size, align(bytes![32; KiB]), Strategy::FirstFit);
+        assert_eq!(found, Some(8..9));
+    }
+
+    #[test]
+    fn allocate_physical() {
+        let mut device: Memory<512, 128> = Memory::default();
+        let disk = Disk::format(&mut device).unwrap();
+        assert_eq!(disk.holes(), vec![34..95]);
+
+        // 4 KiB physical blocks, the first of which starts at block 7
+        let align = Alignment {
+            bytes: Some(512),
+            physical_block_size: Some(bytes![4; KiB]),
+            alignment_offset: Some(7 * 512),
+        };
+
+        let size = bytes![2; KiB];
+        let found = disk.allocate(size, align, Strategy::FirstFit);
+        assert_eq!(found, Some(39..43));
+
+        let found = disk.allocate(size, align, Strategy::AtEnd);
+        assert_eq!(found, Some(87..91));
+
+        let align = Alignment {
+            bytes: Some(u64::MAX),
+            ..align
+        };
+        assert_eq!(disk.allocate(size, align, Strategy::FirstFit), None);
+    }
+
+    #[test]
+    fn large() {
+        // A 2 TiB disk
+        let mut device = Sparse::<512>::new(1 << 32);
+        let kind = Uuid::new_v4();
+
+        let mut disk = Disk::format(&mut device).unwrap();
+        assert_eq!(disk.holes(), vec![34..(1 << 32) - 33]);
+
+        let size = bytes![1; TiB];
+        let align = Alignment::default();
+        let blocks = disk.allocate(size, align, Strategy::AtEnd).unwrap();
+        assert_eq!(blocks, (1 << 31) - 2048..(1 << 32) - 2048);
+        disk.add(kind, blocks.clone(), Attributes::empty(), "big")
+            .unwrap();
+
+        let disk = Disk::load(&mut device).unwrap().unwrap();
+        assert_eq!(disk.partitions().pop().unwrap().blocks(), blocks);
+
+        // Only the headers and entry arrays are stored.
+        assert_eq!(device.resident(), 2 * 33);
+    }
+
+    #[test]
+    fn kind() {
+        let mut device: Memory<512, 128> = Memory::default();
+        let attr = Attributes::REQUIRED | Attributes::READ_ONLY;
+
+        let mut disk = Disk::format(&mut device).unwrap();
+        disk.add(Kind::EFI_SYSTEM.guid, 34..64, attr, "esp")
+            .unwrap();
+
+        // GUIDs are stored in mixed-endian order.
+        let block = device.get(2).unwrap();
+        assert_eq!(
+            block[..16],
+            [
+                0x28, 0x73, 0x2a, 0xc1, 0x1f, 0xf8, 0xd2, 0x11, 0xba, 0x4b, 0x00, 0xa0, 0xc9, 0x3e,
+                0xc9, 0x3b
+            ]
+        );
+
+        let disk = Disk::load(&mut device).unwrap().unwrap();
+        let part = disk.partitions().pop().unwrap();
+        assert_eq!(Kind::from_guid(part.kind()), Some(&Kind::EFI_SYSTEM));
+        assert_eq!(Kind::from_name("efi system"), Some(&Kind::EFI_SYSTEM));
+        assert_eq!(part.attributes(), attr);
+        assert_eq!(part.attributes().type_specific(), 1 << 12);
+        assert!(!part.attributes().contains(Attributes::NO_AUTO));
+    }
+
+    #[test]
+    fn guid_decoding() {
+        let mut device: Memory<512, 128> = Memory::default();
+        let mut disk = Disk::format(&mut device).unwrap();
+        disk.add(Kind::EFI_SYSTEM.guid, 34..64, Attributes::empty(), "esp")
+            .unwrap();
+
+        // 5C5B6A13-0E6F-4B2B-8F4A-6A1D8F5C7E21, as written by other tools
+        let bytes = [
+            0x13, 0x6a, 0x5b, 0x5c, 0x6f, 0x0e, 0x2b, 0x4b, 0x8f, 0x4a, 0x6a, 0x1d, 0x8f, 0x5c,
+            0x7e, 0x21,
+        ];
+        let expected: Uuid = "5C5B6A13-0E6F-4B2B-8F4A-6A1D8F5C7E21".parse().unwrap();
+
+        for index in [2, 95] {
+            let mut block = device.get(index).unwrap();
+            block[16..32].copy_from_slice(&bytes);
+            device.set(index, &block).unwrap();
+        }
+
+        let mut entries = Vec::new();
+        for index in 2..34 {
+            entries.extend_from_slice(&device.get(index).unwrap());
+        }
+
+        let ecrc32 = crc32(&entries);
+        for index in [1, 127] {
+            rewrite(&mut device, index, |h| {
+                h.guid = bytes;
+                h.ecrc32 = ecrc32;
+            });
+        }
+
+        let disk = Disk::load(&mut device).unwrap().unwrap();
+        assert_eq!(disk.guid(), expected);
+        assert_eq!(disk.partition(1).unwrap().guid(), expected);
+        assert_eq!(disk.partition(1).unwrap().kind(), Kind::EFI_SYSTEM.guid);
+    }
+
+    #[test]
+    fn shared() {
+        let mut device: Memory<512, 128> = Memory::default();
+        let kind = Uuid::new_v4();
+
+        let mut disk = Disk::format(&mut device).unwrap();
+        disk.add(kind, 34..64, Attributes::empty(), "foo").unwrap();
+        let mut foo = disk.partitions().pop().unwrap();
+
+        disk.add(kind, 64..95, Attributes::empty(), "bar").unwrap();
+        let mut bar = disk.partitions().pop().unwrap();
+
+        foo.set(0, &[1; 512]).unwrap();
+        bar.set(0, &[2; 512]).unwrap();
+        assert_eq!(foo.get(0).unwrap(), [1; 512]);
+        assert_eq!(bar.get(0).unwrap(), [2; 512]);
+
+        drop((disk, foo, bar));
+        assert_eq!(device.get(34).unwrap(), [1; 512]);
+        assert_eq!(device.get(64).unwrap(), [2; 512]);
+    }
+
+    #[test]
+    fn stale() {
+        let mut device: Memory<512, 128> = Memory::default();
+        let kind = Uuid::new_v4();
+
+        let mut disk = Disk::format(&mut device).unwrap();
+        disk.add(kind, 34..64, Attributes::empty(), "foo").unwrap();
+        let mut foo = disk.partition(1).unwrap();
+        assert_eq!(foo.len(), 30);
+
+        // Handles follow the table, so a shrunk partition can't reach past
+        // its new end.
+        disk.resize(1, 10).unwrap();
+        assert_eq!(foo.blocks(), 34..44);
+        foo.set(9, &[1; 512]).unwrap();
+        assert_eq!(foo.get(10).unwrap_err(), Error::OutOfBounds);
+        assert_eq!(foo.set(10, &[1; 512]).unwrap_err(), Error::OutOfBounds);
+
+        // Nor can a removed one, even once its slot is reused.
+        disk.remove(1).unwrap();
+        disk.add(kind, 34..64, Attributes::empty(), "bar").unwrap();
+        assert_eq!(foo.len(), 0);
+        assert_eq!(foo.get(0).unwrap_err(), Error::OutOfBounds);
+        assert_eq!(disk.partition(1).unwrap().len(), 30);
+    }
+
+    #[test]
+    fn threads() {
+        let device: Memory<512, 128> = Memory::default();
+        let kind = Uuid::new_v4();
+
+        let mut disk = SyncDisk::format(device).unwrap();
+        disk.add(kind, 34..64, Attributes::empty(), "foo").unwrap();
+        disk.add(kind, 64..95, Attributes::empty(), "bar").unwrap();
+
+        let threads: Vec<_> = disk
+            .partitions()
+            .into_iter()
+            .enumerate()
+            .map(|(i, mut part)| {
+                std::thread::spawn(move || {
+                    for j in 0..part.len() {
+                        part.set(j, &[i as u8 + 1; 512]).unwrap();
+                    }
+                })
+            })
+            .collect();
+
+        for thread in threads {
+            thread.join().unwrap();
+        }
+
+        for (i, mut part) in disk.partitions().into_iter().enumerate() {
+            for j in 0..part.len() {
+                assert_eq!(part.get(j).unwrap(), [i as u8 + 1; 512]);
+            }
+        }
+    }
+
     #[test]
     fn zero_head() {
         const ZERO: [u8; 512] = [0; 512];
@@ -334,6 +1609,113 @@ mod test {
         assert_eq!(Disk::load(device).unwrap().unwrap().guid(), guid);
     }
 
+    #[test]
+    fn repair() {
+        let mut device: Memory<512, 128> = Memory::default();
+        let index = device.len() - 1;
+
+        Disk::format(&mut device).unwrap();
+
+        let mut block = device.get(1).unwrap();
+        block[32] = 0xff;
+        device.set(1, &block).unwrap();
+        assert_eq!(device.load(1).unwrap_err(), Error::Corrupted);
+
+        let mut disk = Disk::load(&mut device).unwrap().unwrap();
+        disk.repair().unwrap();
+
+        assert!(device.load(1).unwrap().is_some());
+        assert!(device.load(index).unwrap().is_some());
+    }
+
+    #[test]
+    fn other_lba() {
+        let mut device: Memory<512, 128> = Memory::default();
+        let kind = Uuid::new_v4();
+
+        let mut disk = Disk::format(&mut device).unwrap();
+        disk.add(kind, 34..64, Attributes::empty(), "foo").unwrap();
+
+        for other in [1, 2, 64].iter() {
+            rewrite(&mut device, 1, |h| h.other_lba = *other);
+            assert_eq!(device.load(1).unwrap_err(), Error::OutOfBounds);
+
+            // The backup is used instead, and repairing keeps the primary.
+            let mut disk = Disk::load(&mut device).unwrap().unwrap();
+            disk.repair().unwrap();
+
+            assert_eq!(device.load(1).unwrap().unwrap().header.other_lba, 127);
+            assert_eq!(Disk::check(&mut device).unwrap(), vec![]);
+        }
+    }
+
+    #[test]
+    fn grow() {
+        let mut small: Memory<512, 128> = Memory::default();
+        let mut large: Memory<512, 256> = Memory::default();
+        let kind = Uuid::new_v4();
+
+        let mut disk = Disk::format(&mut small).unwrap();
+        disk.add(kind, 34..64, Attributes::empty(), "foo").unwrap();
+
+        for i in 0..small.len() {
+            large.set(i, &small.get(i).unwrap()).unwrap();
+        }
+
+        let mut disk = Disk::load(&mut large).unwrap().unwrap();
+        assert_eq!(disk.holes(), vec![64..95]);
+
+        disk.relocate_backup().unwrap();
+        assert_eq!(disk.holes(), vec![64..223]);
+
+        let disk = Disk::load(&mut large).unwrap().unwrap();
+        assert_eq!(disk.holes(), vec![64..223]);
+        assert_eq!(disk.partitions().len(), 1);
+
+        assert!(large.load(127).unwrap().is_none());
+        assert!(large.load(255).unwrap().is_some());
+    }
+
+    #[test]
+    fn check() {
+        let mut device: Memory<512, 128> = Memory::default();
+        let index = device.len() - 1;
+        let kind = Uuid::new_v4();
+
+        let mut disk = Disk::format(&mut device).unwrap();
+        disk.add(kind, 34..64, Attributes::empty(), "foo").unwrap();
+        assert_eq!(Disk::check(&mut device).unwrap(), vec![]);
+
+        let mut block = device.get(2).unwrap();
+        block[0] ^= 0xff;
+        device.set(2, &block).unwrap();
+        device.set(index, &[0; 512]).unwrap();
+
+        assert_eq!(
+            Disk::check(&mut device).unwrap(),
+            vec![
+                Finding::Primary(Problem::EntryCrc),
+                Finding::Backup(Problem::Missing),
+            ]
+        );
+    }
+
+    #[test]
+    fn check_grown() {
+        let mut small: Memory<512, 128> = Memory::default();
+        let mut large: Memory<512, 256> = Memory::default();
+
+        Disk::format(&mut small).unwrap();
+        for i in 0..small.len() {
+            large.set(i, &small.get(i).unwrap()).unwrap();
+        }
+
+        assert_eq!(
+            Disk::check(&mut large).unwrap(),
+            vec![Finding::Backup(Problem::Misplaced(127))]
+        );
+    }
+
     #[test]
     fn corrupt_both() {
         let mut device: Memory<512, 128> = Memory::default();
@@ -1,7 +1,7 @@
 use quoin_codec::codec;
 
 codec! {
-    #[derive(Copy, Clone, Debug)]
+    #[derive(Copy, Clone, Debug, PartialEq, Eq)]
     pub struct Range {
         pub first: u64,
         pub last: u64,
@@ -21,6 +21,6 @@ impl Range {
 
     #[inline]
     pub fn overlaps(&self, range: Range) -> bool {
-        self.includes(range.first) || self.includes(range.last)
+        self.first <= range.last && range.first <= self.last
     }
 }
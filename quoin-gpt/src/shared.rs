@@ -0,0 +1,77 @@
+use super::Entry;
+
+use std::cell::RefCell;
+use std::ops::Range;
+use std::rc::Rc;
+use std::sync::{Arc, Mutex};
+
+/// A handle through which a disk and its partitions share one device
+///
+/// Each access borrows the device for the duration of a single operation,
+/// so any number of partitions may be used at the same time.
+pub trait Shared<T>: Clone {
+    fn new(device: T) -> Self;
+    fn with<R>(&self, func: impl FnOnce(&mut T) -> R) -> R;
+}
+
+impl<T> Shared<T> for Rc<RefCell<T>> {
+    #[inline]
+    fn new(device: T) -> Self {
+        Rc::new(RefCell::new(device))
+    }
+
+    #[inline]
+    fn with<R>(&self, func: impl FnOnce(&mut T) -> R) -> R {
+        func(&mut self.borrow_mut())
+    }
+}
+
+impl<T> Shared<T> for Arc<Mutex<T>> {
+    #[inline]
+    fn new(device: T) -> Self {
+        Arc::new(Mutex::new(device))
+    }
+
+    #[inline]
+    fn with<R>(&self, func: impl FnOnce(&mut T) -> R) -> R {
+        func(&mut self.lock().unwrap())
+    }
+}
+
+/// The device and table entries that a disk shares with its partitions
+///
+/// Partitions look up their entry on every access, so they always see the
+/// table as it was last written.
+pub struct State<T> {
+    pub(crate) device: T,
+    pub(crate) entries: Vec<Entry>,
+}
+
+impl<T> State<T> {
+    // Finds the entry of a partition, unless it has since been removed.
+    pub(crate) fn entry(&self, number: u32, guid: [u8; 16]) -> Option<&Entry> {
+        let slot = (number as usize).checked_sub(1)?;
+        let entry = self.entries.get(slot)?;
+        match entry.kind != Entry::EMPTY && entry.guid == guid {
+            true => Some(entry),
+            false => None,
+        }
+    }
+
+    // The blocks of a partition, of which there are none once it is removed.
+    pub(crate) fn blocks(&self, number: u32, guid: [u8; 16]) -> Range<u64> {
+        match self.entry(number, guid) {
+            Some(entry) => entry.data.first..entry.data.last + 1,
+            None => 0..0,
+        }
+    }
+
+    // Translates a block of a partition into a block of the device.
+    pub(crate) fn block(&self, number: u32, guid: [u8; 16], index: u64) -> Option<u64> {
+        let blocks = self.blocks(number, guid);
+        match index < blocks.end - blocks.start {
+            true => Some(blocks.start + index),
+            false => None,
+        }
+    }
+}
@@ -1,5 +1,6 @@
-use super::{EntriesExt, Entry, Error, Header, Range};
+use super::{EntriesExt, Entry, Error, Header, Problem, Range};
 
+use std::cmp::max;
 use std::convert::TryInto;
 
 use quoin_codec::Codec;
@@ -13,20 +14,37 @@ pub struct Table {
     pub entries: Vec<Entry>,
 }
 
+/// The results of validating one copy of the table
+///
+/// The entries of the table include empty slots.
+#[derive(Clone, Debug)]
+pub struct Scan {
+    pub table: Option<Table>,
+    pub problems: Vec<Problem>,
+}
+
+/// Where a table is placed on the device
+#[derive(Copy, Clone, Debug)]
+pub struct Placement {
+    pub guid: [u8; 16],
+    pub usable: Range,
+    pub elba: u64,
+    pub slots: u32,
+}
+
 pub trait DeviceExt<const SIZE: usize>: Device<SIZE> {
-    fn save(
-        &mut self,
-        guid: [u8; 16],
-        usable: Option<Range>,
-        entries: &[Entry],
-    ) -> Result<(), Error<Self::Error>> {
-        let mut ebuffer = Vec::with_capacity(<[Entry]>::MIN_SIZE);
+    fn save(&mut self, placement: &Placement, entries: &[Entry]) -> Result<(), Error<Self::Error>> {
+        let ecount = max(placement.slots as usize, entries.len());
+        let urange = placement.usable;
+
+        let mut ebuffer = Vec::with_capacity(max(Entry::MIN_ARRAY, ecount * Entry::SIZE));
         for e in entries {
             e.encode(&mut ebuffer).unwrap();
         }
 
+        ebuffer.resize(ecount * Entry::SIZE, 0u8);
         let ecrc32 = crc32(&ebuffer[..]);
-        while ebuffer.len() < <[Entry]>::MIN_SIZE || ebuffer.len() % SIZE != 0 {
+        while ebuffer.len() < Entry::MIN_ARRAY || ebuffer.len() % SIZE != 0 {
             ebuffer.push(0u8);
         }
 
@@ -35,13 +53,14 @@ pub trait DeviceExt<const SIZE: usize>: Device<SIZE> {
             return Err(Error::OutOfBounds);
         }
 
-        let mrange = Range {
-            first: 2 + eblocks.len() as u64,
-            last: self.len() - 2 - eblocks.len() as u64,
-        };
-
-        let urange = usable.unwrap_or(mrange);
-        if !mrange.contains(urange) {
+        // The primary entries must fit between its header and the usable
+        // range. The backup entries immediately precede the backup header.
+        let elba = self.len() - 1 - eblocks.len() as u64;
+        if placement.elba < 2
+            || placement.elba + eblocks.len() as u64 > urange.first
+            || urange.first > urange.last
+            || urange.last >= elba
+        {
             return Err(Error::OutOfBounds);
         }
 
@@ -56,9 +75,9 @@ pub trait DeviceExt<const SIZE: usize>: Device<SIZE> {
             this_lba: 1,
             other_lba: self.len() - 1,
             usable: urange,
-            guid,
-            elba: 2,
-            ecount: entries.len().try_into().unwrap(),
+            guid: placement.guid,
+            elba: placement.elba,
+            ecount: ecount.try_into().unwrap(),
             esize: Entry::SIZE as u32,
             ecrc32,
         };
@@ -72,9 +91,9 @@ pub trait DeviceExt<const SIZE: usize>: Device<SIZE> {
             this_lba: self.len() - 1,
             other_lba: 1,
             usable: urange,
-            guid,
-            elba: self.len() - 1 - eblocks.len() as u64,
-            ecount: entries.len().try_into().unwrap(),
+            guid: placement.guid,
+            elba,
+            ecount: ecount.try_into().unwrap(),
             esize: Entry::SIZE as u32,
             ecrc32,
         };
@@ -90,19 +109,32 @@ pub trait DeviceExt<const SIZE: usize>: Device<SIZE> {
         tail.encode(&mut tbuf[..]).unwrap();
 
         self.set(head.this_lba, &hbuf)?;
-        for i in 0..eblocks.len() {
-            self.set(head.elba + i as u64, &eblocks[i])?;
+        for (i, block) in eblocks.iter().enumerate() {
+            self.set(head.elba + i as u64, block)?;
         }
 
-        for i in 0..eblocks.len() {
-            self.set(tail.elba + i as u64, &eblocks[i])?;
+        for (i, block) in eblocks.iter().enumerate() {
+            self.set(tail.elba + i as u64, block)?;
         }
         self.set(tail.this_lba, &tbuf)?;
+        self.flush()?;
 
         Ok(())
     }
 
     fn load(&mut self, index: u64) -> Result<Option<Table>, Error<Self::Error>> {
+        let scan = self.scan(index)?;
+
+        match (scan.table, scan.problems.first()) {
+            (None, ..) => Ok(None),
+            (.., Some(problem)) => Err(problem.error()),
+            (Some(table), None) => Ok(Some(table)),
+        }
+    }
+
+    fn scan(&mut self, index: u64) -> Result<Scan, Self::Error> {
+        let mut problems = Vec::new();
+
         // The range of all disk blocks
         let drange = Range {
             first: 0,
@@ -115,99 +147,125 @@ pub trait DeviceExt<const SIZE: usize>: Device<SIZE> {
             last: drange.last - 1,
         };
 
-        // Determine the number of entries per block
-        let epb = SIZE / Entry::SIZE;
+        // Load the header
+        let mut block = self.get(index)?;
+        let header = Header::decode(&block[..]).unwrap();
 
-        // Determine the correct location of the "other" copy of the table
+        // Determine the correct location of the "other" copy of the table.
+        // The backup normally lives in the last block, but it may precede it
+        // if the device has grown since the table was written.
         let other = match index {
-            1 => self.len() - 1,
+            1 => header.other_lba,
             _ => 1,
         };
 
-        // Load the header
-        let mut block = self.get(index)?;
-        let header = Header::decode(&block[..]).unwrap();
-
         if header.signature != Header::SIGNATURE {
-            return Ok(None);
+            return Ok(Scan {
+                table: None,
+                problems: vec![Problem::Missing],
+            });
         }
 
         if header.revision != Header::REVISION {
-            return Err(Error::Unsupported);
+            problems.push(Problem::Revision(header.revision));
         }
 
-        if header.size as usize != Header::SIZE {
-            return Err(Error::Unsupported);
+        // The header may be larger than the fields we know about.
+        let hsize = header.size as usize;
+        if hsize < Header::SIZE || hsize > SIZE {
+            problems.push(Problem::HeaderSize(header.size));
         }
 
-        if header.esize as usize != Entry::SIZE {
-            return Err(Error::Unsupported);
+        // Entries may be larger than the fields we know about.
+        let esize = header.esize as usize;
+        if esize < Entry::SIZE || !esize.is_multiple_of(Entry::SIZE) {
+            problems.push(Problem::EntrySize(header.esize));
         }
 
         if header.reserved != 0 {
-            return Err(Error::Unsupported);
+            problems.push(Problem::Reserved(header.reserved));
         }
 
         let mut hdr = header.clone();
         hdr.crc32 = 0;
         hdr.encode(&mut block[..]).unwrap();
-        if crc32(&block[..Header::SIZE]) != header.crc32 {
-            return Err(Error::Corrupted);
+        let trusted = crc32(&block[..hsize.clamp(Header::SIZE, SIZE)]) == header.crc32;
+        if !trusted {
+            problems.push(Problem::HeaderCrc);
         }
 
         if header.this_lba != index {
-            return Err(Error::OutOfBounds);
+            problems.push(Problem::ThisLba(header.this_lba));
         }
 
-        if header.other_lba != other {
-            return Err(Error::OutOfBounds);
-        }
-
-        // Calculate the usable range
-        let block_count = (header.ecount as usize + epb - 1) / epb;
-        let urange = Range {
-            first: erange.first + block_count as u64,
-            last: erange.last - block_count as u64,
+        // The range of blocks occupied by the entries
+        let length = header.ecount as usize * esize;
+        let block_count = length.div_ceil(SIZE);
+        let arange = Range {
+            first: header.elba,
+            last: header
+                .elba
+                .saturating_add(block_count.saturating_sub(1) as u64),
         };
 
-        if header.usable.first > header.usable.last {
-            return Err(Error::OutOfBounds);
+        // The backup must follow the usable range, so it can never be this
+        // header or land in its entries. The backup checks its own entries.
+        if header.other_lba != other
+            || header.other_lba == index
+            || !drange.includes(header.other_lba)
+            || (index == 1 && header.other_lba <= header.usable.last)
+            || (block_count > 0 && arange.includes(header.other_lba))
+        {
+            problems.push(Problem::OtherLba(header.other_lba));
         }
 
-        if !urange.contains(header.usable) {
-            return Err(Error::OutOfBounds);
+        if header.usable.first > header.usable.last
+            || !erange.contains(header.usable)
+            || header.usable.includes(index)
+        {
+            problems.push(Problem::Usable);
         }
 
-        if !erange.includes(header.elba) {
-            return Err(Error::OutOfBounds);
-        }
-
-        if block_count > 0 && urange.includes(header.elba) {
-            return Err(Error::OutOfBounds);
-        }
+        // Other tools may place the entries anywhere between the header and
+        // the usable range, so long as they stay on their side of it.
+        let placed = erange.includes(header.elba) && (block_count == 0 || erange.contains(arange));
+        let ordered = match index {
+            1 => arange.last < header.usable.first,
+            _ => header.usable.last < arange.first && arange.last < index,
+        };
 
-        // Load the entry blocks.
-        let mut blocks = vec![[0u8; SIZE]; block_count];
-        for i in 0..blocks.len() {
-            blocks[i] = self.get(header.elba + i as u64)?;
+        if !placed || !ordered || (block_count > 0 && header.usable.overlaps(arange)) {
+            problems.push(Problem::EntryLba(header.elba));
         }
 
-        // Check that the blocks haven't been modified.
-        let buffer = unsafe { blocks.align_to::<u8>().1 };
-        let length = header.ecount as usize * Entry::SIZE;
-        if crc32(&buffer[..length]) != header.ecrc32 {
-            return Err(Error::Corrupted);
+        // Only read the entries if we can trust where they are.
+        let mut entries = Vec::new();
+        if trusted && placed && esize >= Entry::SIZE && esize.is_multiple_of(Entry::SIZE) {
+            // Load the entry blocks.
+            let mut blocks = vec![[0u8; SIZE]; block_count];
+            for (i, block) in blocks.iter_mut().enumerate() {
+                *block = self.get(header.elba + i as u64)?;
+            }
+
+            // Check that the blocks haven't been modified.
+            let buffer = unsafe { blocks.align_to::<u8>().1 };
+            if crc32(&buffer[..length]) != header.ecrc32 {
+                problems.push(Problem::EntryCrc);
+            }
+
+            // Decode the entry blocks, keeping the empty slots.
+            entries = buffer[..length]
+                .chunks(esize)
+                .map(|x| Entry::decode(&x[..Entry::SIZE]).unwrap())
+                .collect();
+
+            problems.extend(entries.problems(header.usable));
         }
 
-        // Decode the entry blocks.
-        let entries: Vec<Entry> = buffer
-            .chunks(Entry::SIZE)
-            .map(|x| Entry::decode(x).unwrap())
-            .filter(|e| e.kind != Entry::EMPTY)
-            .collect();
-
-        entries.validate(urange)?;
-        Ok(Some(Table { header, entries }))
+        Ok(Scan {
+            table: Some(Table { header, entries }),
+            problems,
+        })
     }
 }
 
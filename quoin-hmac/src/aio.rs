@@ -0,0 +1,154 @@
+//! Asynchronous block authentication
+
+use super::{sign, verify, Error, Hash};
+
+use quoin_device::AsyncDevice;
+
+use std::pin::Pin;
+
+use openssl::pkey::{PKey, Private};
+
+type Future<'a, T, E> = Pin<Box<dyn std::future::Future<Output = Result<T, E>> + Send + 'a>>;
+
+/// Authenticates each block of an [AsyncDevice]
+///
+/// The blocks are compatible with those of [super::Hmac].
+pub struct Hmac<T: AsyncDevice<SIZE>, H: Hash, const SIZE: usize> {
+    device: T,
+    secret: PKey<Private>,
+    digest: H,
+}
+
+impl<T: AsyncDevice<SIZE>, H: Hash, const SIZE: usize> Hmac<T, H, SIZE> {
+    #[inline]
+    pub fn new<K: AsRef<[u8]>>(device: T, key: K, hash: H) -> Result<Self, Error<T::Error>> {
+        assert_eq!(key.as_ref().len(), H::SIZE);
+        assert_eq!(SIZE % H::SIZE, 0);
+        assert!(SIZE > H::SIZE);
+
+        Ok(Self {
+            device,
+            secret: PKey::hmac(key.as_ref())?,
+            digest: hash,
+        })
+    }
+}
+
+// The operations take the smaller block size as a separate parameter, since
+// `SIZE - H::SIZE` cannot be used in the state of a future.
+async fn read<T: AsyncDevice<SIZE>, H: Hash, const SIZE: usize, const BODY: usize>(
+    hmac: &mut Hmac<T, H, SIZE>,
+    index: u64,
+    mut block: Box<[u8; BODY]>,
+) -> Result<Box<[u8; BODY]>, Error<T::Error>> {
+    let buffer = Box::new([0u8; SIZE]);
+    let buffer = hmac
+        .device
+        .read(index, buffer)
+        .await
+        .map_err(Error::Parent)?;
+    verify(hmac.digest, &hmac.secret, index, &buffer[..])?;
+
+    block.copy_from_slice(&buffer[..BODY]);
+    Ok(block)
+}
+
+async fn write<T: AsyncDevice<SIZE>, H: Hash, const SIZE: usize, const BODY: usize>(
+    hmac: &mut Hmac<T, H, SIZE>,
+    index: u64,
+    block: Box<[u8; BODY]>,
+) -> Result<Box<[u8; BODY]>, Error<T::Error>> {
+    let mut buffer = Box::new([0u8; SIZE]);
+    let (body, tag) = buffer.split_at_mut(BODY);
+    body.copy_from_slice(&block[..]);
+    sign(hmac.digest, &hmac.secret, index, &block[..], tag)?;
+
+    hmac.device
+        .write(index, buffer)
+        .await
+        .map_err(Error::Parent)?;
+    Ok(block)
+}
+
+async fn flush<T: AsyncDevice<SIZE>, H: Hash, const SIZE: usize>(
+    hmac: &mut Hmac<T, H, SIZE>,
+) -> Result<(), Error<T::Error>> {
+    hmac.device.flush().await.map_err(Error::Parent)
+}
+
+// The methods return the futures above, since `async_trait` would create
+// futures within the scope of `SIZE - H::SIZE`.
+impl<T: AsyncDevice<SIZE>, H: Hash + Send, const SIZE: usize> AsyncDevice<{ SIZE - H::SIZE }>
+    for Hmac<T, H, SIZE>
+{
+    type Error = Error<T::Error>;
+
+    #[inline]
+    fn len(&self) -> u64 {
+        self.device.len()
+    }
+
+    fn read<'a, 'b>(
+        &'a mut self,
+        index: u64,
+        block: Box<[u8; SIZE - H::SIZE]>,
+    ) -> Future<'b, Box<[u8; SIZE - H::SIZE]>, Self::Error>
+    where
+        'a: 'b,
+        Self: 'b,
+    {
+        Box::pin(read(self, index, block))
+    }
+
+    fn write<'a, 'b>(
+        &'a mut self,
+        index: u64,
+        block: Box<[u8; SIZE - H::SIZE]>,
+    ) -> Future<'b, Box<[u8; SIZE - H::SIZE]>, Self::Error>
+    where
+        'a: 'b,
+        Self: 'b,
+    {
+        Box::pin(write(self, index, block))
+    }
+
+    fn flush<'a, 'b>(&'a mut self) -> Future<'b, (), Self::Error>
+    where
+        'a: 'b,
+        Self: 'b,
+    {
+        Box::pin(flush(self))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    use crate::Sha256;
+
+    use quoin_blocking::Blocking;
+    use quoin_memory::Memory;
+
+    #[tokio::test]
+    async fn hmac() {
+        const BLOCK: [u8; 480] = [0xff; 480];
+
+        let key = [0; 32];
+        let mut device = Blocking::new(Memory::<512, 1>::default());
+
+        let mut hs256 = Hmac::new(&mut device, key, Sha256).unwrap();
+        hs256.write(0, Box::new(BLOCK)).await.unwrap();
+        let block = hs256.read(0, Box::new([0; 480])).await.unwrap();
+        assert_eq!(*block, BLOCK);
+
+        // Modify the raw block.
+        let mut raw = device.read(0, Box::new([0; 512])).await.unwrap();
+        raw[7] ^= 1;
+        device.write(0, raw).await.unwrap();
+
+        let mut hs256 = Hmac::new(&mut device, key, Sha256).unwrap();
+        let err = hs256.read(0, block).await.unwrap_err();
+        assert!(matches!(err, Error::BlockModified));
+    }
+}
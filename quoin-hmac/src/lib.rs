@@ -1,8 +1,12 @@
 #![allow(incomplete_features)]
-#![feature(const_generics)]
-#![feature(const_evaluatable_checked)]
+#![feature(generic_const_exprs)]
 
-use quoin_device::Device;
+#[cfg(feature = "async")]
+pub mod aio;
+
+use quoin_device::{BlockSize, Device, DynDevice, DynError, ErrorKind};
+
+use std::fmt::{Display, Formatter};
 
 use openssl::error::ErrorStack;
 use openssl::hash::MessageDigest;
@@ -24,8 +28,13 @@ mod sealed {
 
 #[derive(Clone, Debug)]
 pub enum Error<T> {
+    /// The underlying device failed
     Parent(T),
+
+    /// The MAC could not be computed
     Crypto(ErrorStack),
+
+    /// The block does not match its MAC
     BlockModified,
 }
 
@@ -36,6 +45,36 @@ impl<T> From<ErrorStack> for Error<T> {
     }
 }
 
+impl<T> Display for Error<T> {
+    fn fmt(&self, f: &mut Formatter) -> std::fmt::Result {
+        f.write_str(match self {
+            Self::Parent(..) => "device error",
+            Self::Crypto(..) => "unable to compute the block MAC",
+            Self::BlockModified => "the block has been modified",
+        })
+    }
+}
+
+impl<T: std::error::Error + 'static> std::error::Error for Error<T> {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Self::Parent(e) => Some(e),
+            Self::Crypto(e) => Some(e),
+            Self::BlockModified => None,
+        }
+    }
+}
+
+impl<T: quoin_device::Error> quoin_device::Error for Error<T> {
+    fn kind(&self) -> ErrorKind {
+        match self {
+            Self::Parent(e) => e.kind(),
+            Self::Crypto(..) => ErrorKind::Other,
+            Self::BlockModified => ErrorKind::Integrity,
+        }
+    }
+}
+
 #[derive(Copy, Clone, Debug)]
 pub struct Sha256;
 
@@ -72,6 +111,41 @@ impl Hash for Sha512 {
     }
 }
 
+// Computes the tag of the block at `index`.
+fn sign<H: Hash>(
+    hash: H,
+    secret: &PKey<Private>,
+    index: u64,
+    body: &[u8],
+    tag: &mut [u8],
+) -> Result<(), ErrorStack> {
+    let mut signer = Signer::new(hash.digest(), secret)?;
+    signer.update(&index.to_le_bytes())?;
+    signer.update(body)?;
+    signer.sign(tag)?;
+    Ok(())
+}
+
+// Checks the tag at the end of the block at `index`.
+fn verify<H: Hash, E>(
+    hash: H,
+    secret: &PKey<Private>,
+    index: u64,
+    block: &[u8],
+) -> Result<(), Error<E>> {
+    let (body, tag) = block.split_at(block.len() - H::SIZE);
+
+    // Large enough for the tag of any supported hash
+    let mut ver = [0u8; 64];
+    sign(hash, secret, index, body, &mut ver[..H::SIZE])?;
+
+    if !memcmp::eq(tag, &ver[..H::SIZE]) {
+        return Err(Error::BlockModified);
+    }
+
+    Ok(())
+}
+
 pub struct Hmac<T: Device<SIZE>, H: Hash, const SIZE: usize> {
     device: T,
     secret: PKey<Private>,
@@ -108,37 +182,76 @@ impl<T: Device<SIZE>, H: Hash, const SIZE: usize> Device<{ SIZE - H::SIZE }> for
             Err(e) => return Err(Error::Parent(e)),
         };
 
-        let (body, tag) = block.split_at(SIZE - H::SIZE);
+        verify(self.digest, &self.secret, index, &block)?;
+
+        let body = &block[..SIZE - H::SIZE];
+        Ok(unsafe { body.align_to::<[u8; SIZE - H::SIZE]>().1[0] })
+    }
+
+    #[inline]
+    fn set(&mut self, index: u64, block: &[u8; SIZE - H::SIZE]) -> Result<(), Self::Error> {
+        let mut buffer = [0u8; SIZE];
+        let (body, tag) = buffer.split_at_mut(SIZE - H::SIZE);
+        body.copy_from_slice(block);
+
+        sign(self.digest, &self.secret, index, block, tag)?;
 
-        let mut signer = Signer::new(self.digest.digest(), &self.secret)?;
-        signer.update(&index.to_le_bytes())?;
-        signer.update(&body)?;
+        self.device.set(index, &buffer).map_err(Error::Parent)
+    }
 
-        let mut ver = [0u8; SIZE];
-        signer.sign(&mut ver[..H::SIZE])?;
+    #[inline]
+    fn flush(&mut self) -> Result<(), Self::Error> {
+        self.device.flush().map_err(Error::Parent)
+    }
+}
 
-        if !memcmp::eq(&tag, &ver[..H::SIZE]) {
-            return Err(Error::BlockModified);
-        }
+/// Exposes an [Hmac] as a [DynDevice]
+///
+/// Unlike [quoin_device::Dyn], this needs no constant expressions, so crates
+/// can use an [Hmac] without enabling `generic_const_exprs`.
+pub struct DynHmac<T: Device<SIZE>, H: Hash, const SIZE: usize>(pub Hmac<T, H, SIZE>);
 
-        Ok(unsafe { body.align_to::<[u8; SIZE - H::SIZE]>().1[0] })
+impl<T: Device<SIZE>, H: Hash, const SIZE: usize> DynDevice for DynHmac<T, H, SIZE> {
+    #[inline]
+    fn block_size(&self) -> usize {
+        SIZE - H::SIZE
     }
 
     #[inline]
-    fn set(&mut self, index: u64, block: &[u8; SIZE - H::SIZE]) -> Result<(), Self::Error> {
+    fn len(&self) -> u64 {
+        self.0.device.len()
+    }
+
+    fn get(&mut self, index: u64, block: &mut [u8]) -> Result<(), DynError> {
+        BlockSize::check(SIZE - H::SIZE, block.len()).map_err(DynError::new)?;
+
+        let buffer = self.0.device.get(index);
+        let buffer = buffer.map_err(|e| DynError::new(Error::Parent(e)))?;
+        verify::<H, T::Error>(self.0.digest, &self.0.secret, index, &buffer)
+            .map_err(DynError::new)?;
+
+        block.copy_from_slice(&buffer[..SIZE - H::SIZE]);
+        Ok(())
+    }
+
+    fn set(&mut self, index: u64, block: &[u8]) -> Result<(), DynError> {
+        BlockSize::check(SIZE - H::SIZE, block.len()).map_err(DynError::new)?;
+
         let mut buffer = [0u8; SIZE];
         let (body, tag) = buffer.split_at_mut(SIZE - H::SIZE);
         body.copy_from_slice(block);
 
-        let mut signer = openssl::sign::Signer::new(self.digest.digest(), &self.secret)?;
-        signer.update(&index.to_le_bytes())?;
-        signer.update(block)?;
-        signer.sign(tag)?;
+        let signed = sign(self.0.digest, &self.0.secret, index, block, tag);
+        signed.map_err(|e| DynError::new(Error::<T::Error>::Crypto(e)))?;
 
-        match self.device.set(index, &buffer) {
-            Err(e) => return Err(Error::Parent(e)),
-            Ok(()) => Ok(()),
-        }
+        let result = self.0.device.set(index, &buffer);
+        result.map_err(|e| DynError::new(Error::Parent(e)))
+    }
+
+    #[inline]
+    fn flush(&mut self) -> Result<(), DynError> {
+        let result = self.0.device.flush();
+        result.map_err(|e| DynError::new(Error::Parent(e)))
     }
 }
 
@@ -158,7 +271,7 @@ mod test {
 
         let memory: Memory<512, 1> = Memory::default();
         let corrupt = Corrupt::new(memory, 0.1);
-        let mut hs256 = Hmac::new(corrupt, &key, Sha256).unwrap();
+        let mut hs256 = Hmac::new(corrupt, key, Sha256).unwrap();
         hs256.set(0, &BLOCK).unwrap();
 
         let mut corrupted = 0;
@@ -175,4 +288,29 @@ mod test {
         assert!(percent > 0.09);
         assert!(percent < 0.11);
     }
+
+    #[test]
+    fn dynamic() {
+        let mut memory: Memory<512, 2> = Memory::default();
+        let hmac = Hmac::new(&mut memory, [0; 64], Sha512).unwrap();
+        let mut hmac = DynHmac(hmac);
+        assert_eq!(hmac.block_size(), 448);
+
+        hmac.set(1, &[0xff; 448]).unwrap();
+        let mut block = [0u8; 448];
+        hmac.get(1, &mut block).unwrap();
+        assert_eq!(block, [0xff; 448]);
+
+        let err = hmac.get(1, &mut [0u8; 512]).unwrap_err();
+        assert!(err.downcast_ref::<BlockSize>().is_some());
+
+        drop(hmac);
+        let mut raw = memory.get(1).unwrap();
+        raw[0] ^= 1;
+        memory.set(1, &raw).unwrap();
+
+        let mut hmac = DynHmac(Hmac::new(&mut memory, [0; 64], Sha512).unwrap());
+        let err = hmac.get(1, &mut block).unwrap_err();
+        assert_eq!(quoin_device::Error::kind(&err), ErrorKind::Integrity);
+    }
 }
@@ -0,0 +1,142 @@
+//! An asynchronous journal
+
+use super::{record, target};
+
+use quoin_device::AsyncDevice;
+
+use async_trait::async_trait;
+
+/// Prevents torn writes of the (larger) blocks of an [AsyncDevice]
+///
+/// The layout on disk is compatible with that of [super::Journal].
+pub struct Journal<T: AsyncDevice<LOWER>, const LOWER: usize, const UPPER: usize> {
+    device: T,
+}
+
+impl<T: AsyncDevice<LOWER>, const LOWER: usize, const UPPER: usize> Journal<T, LOWER, UPPER> {
+    async fn pull(
+        &mut self,
+        index: u64,
+        mut block: Box<[u8; UPPER]>,
+    ) -> Result<Box<[u8; UPPER]>, T::Error> {
+        let mut buffer = Box::new([0u8; LOWER]);
+
+        let count = UPPER / LOWER;
+        for (i, chunk) in block.chunks_exact_mut(LOWER).enumerate() {
+            let idx = index * count as u64 + i as u64;
+            buffer = self.device.read(idx, buffer).await?;
+            chunk.copy_from_slice(&buffer[..]);
+        }
+
+        Ok(block)
+    }
+
+    async fn push(&mut self, index: u64, block: &[u8; UPPER]) -> Result<(), T::Error> {
+        let mut buffer = Box::new([0u8; LOWER]);
+
+        let count = UPPER / LOWER;
+        for (i, chunk) in block.chunks_exact(LOWER).enumerate() {
+            let idx = index * count as u64 + i as u64;
+            buffer.copy_from_slice(chunk);
+            buffer = self.device.write(idx, buffer).await?;
+        }
+
+        Ok(())
+    }
+
+    /// Creates a new journal instance
+    ///
+    /// **NOTE WELL**: You **MUST** immediately call [Journal::replay] in order
+    /// to enforce the tear-free guarantee.
+    #[inline]
+    pub fn new(device: T) -> Self {
+        Self { device }
+    }
+
+    /// Attempts to replay the journal
+    pub async fn replay(&mut self) -> Result<(), T::Error> {
+        assert_eq!(UPPER % LOWER, 0);
+
+        let meta = self.pull(0, Box::new([0u8; UPPER])).await?;
+        let data = self.pull(1, Box::new([0u8; UPPER])).await?;
+
+        if let Some(idx) = target(&meta, &data) {
+            self.push(idx + 2, &data).await?;
+        }
+
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl<T: AsyncDevice<LOWER>, const LOWER: usize, const UPPER: usize> AsyncDevice<UPPER>
+    for Journal<T, LOWER, UPPER>
+{
+    type Error = T::Error;
+
+    #[inline]
+    fn len(&self) -> u64 {
+        self.device.len() / (UPPER / LOWER) as u64 - 2
+    }
+
+    async fn read(
+        &mut self,
+        index: u64,
+        block: Box<[u8; UPPER]>,
+    ) -> Result<Box<[u8; UPPER]>, Self::Error> {
+        self.pull(index + 2, block).await
+    }
+
+    async fn write(
+        &mut self,
+        index: u64,
+        block: Box<[u8; UPPER]>,
+    ) -> Result<Box<[u8; UPPER]>, Self::Error> {
+        let meta = Box::new(record(index, &block));
+
+        self.push(0, &meta).await?;
+        self.push(1, &block).await?;
+        self.push(index + 2, &block).await?;
+
+        Ok(block)
+    }
+
+    #[inline]
+    async fn flush(&mut self) -> Result<(), Self::Error> {
+        self.device.flush().await
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    use quoin_blocking::Blocking;
+    use quoin_device::Device;
+    use quoin_memory::Memory;
+
+    #[tokio::test]
+    async fn journal() {
+        let mut device = Blocking::new(Memory::<512, 10>::default());
+
+        let mut jrnl: Journal<_, 512, 1024> = Journal::new(&mut device);
+        jrnl.replay().await.unwrap();
+        assert_eq!(jrnl.len(), 3);
+
+        let block = jrnl.write(1, Box::new([0xaa; 1024])).await.unwrap();
+        let block = jrnl.read(1, block).await.unwrap();
+        assert_eq!(block[..], [0xaa; 1024][..]);
+
+        // Simulate a crash before the block itself was written.
+        let mut memory: Memory<512, 10> = Memory::default();
+        for i in 0..4 {
+            let block = device.read(i, Box::new([0; 512])).await.unwrap();
+            memory.set(i, &block).unwrap();
+        }
+
+        // The synchronous journal completes the write.
+        let mut jrnl: crate::Journal<_, 512, 1024> = crate::Journal::new(memory);
+        jrnl.replay().unwrap();
+        assert_eq!(jrnl.get(1).unwrap()[..], [0xaa; 1024][..]);
+    }
+}
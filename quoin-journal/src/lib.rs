@@ -1,18 +1,80 @@
-use quoin_device::Device;
+#[cfg(feature = "async")]
+pub mod aio;
 
+use quoin_device::{Device, ErrorKind};
+
+use std::fmt::{Display, Formatter};
 use std::hash::Hasher;
 
 use crc::crc64::{Digest, ISO};
 
 #[derive(Copy, Clone, Debug)]
 pub enum Error<T> {
-    Inner(T),
+    /// The underlying device failed
+    Parent(T),
+
+    /// The journal block size is not a multiple of the device block size
     IncompatibleBlockSize,
 }
 
 impl<T> From<T> for Error<T> {
     fn from(value: T) -> Self {
-        Self::Inner(value)
+        Self::Parent(value)
+    }
+}
+
+impl<T> Display for Error<T> {
+    fn fmt(&self, f: &mut Formatter) -> std::fmt::Result {
+        f.write_str(match self {
+            Self::Parent(..) => "device error",
+            Self::IncompatibleBlockSize => "incompatible block size",
+        })
+    }
+}
+
+impl<T: std::error::Error + 'static> std::error::Error for Error<T> {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Self::Parent(e) => Some(e),
+            Self::IncompatibleBlockSize => None,
+        }
+    }
+}
+
+impl<T: quoin_device::Error> quoin_device::Error for Error<T> {
+    fn kind(&self) -> ErrorKind {
+        match self {
+            Self::Parent(e) => e.kind(),
+            Self::IncompatibleBlockSize => ErrorKind::Other,
+        }
+    }
+}
+
+// Creates the metadata block that journals a write of `block` to `index`.
+fn record<const UPPER: usize>(index: u64, block: &[u8; UPPER]) -> [u8; UPPER] {
+    let mut digest = Digest::new(ISO);
+    digest.write(&index.to_le_bytes());
+    digest.write(block);
+    let crc = digest.finish();
+
+    let mut meta = [0u8; UPPER];
+    meta[..8].copy_from_slice(&index.to_le_bytes());
+    meta[8..][..8].copy_from_slice(&crc.to_le_bytes());
+    meta
+}
+
+// Returns the index of the journaled block, if the journal is intact.
+fn target<const UPPER: usize>(meta: &[u8; UPPER], data: &[u8; UPPER]) -> Option<u64> {
+    let idx = u64::from_le_bytes(unsafe { meta.align_to::<[u8; 8]>().1[0] });
+    let crc = u64::from_le_bytes(unsafe { meta.align_to::<[u8; 8]>().1[1] });
+
+    let mut digest = Digest::new(ISO);
+    digest.write(&idx.to_le_bytes());
+    digest.write(data);
+
+    match digest.finish() == crc {
+        true => Some(idx),
+        false => None,
     }
 }
 
@@ -62,18 +124,27 @@ impl<T: Device<LOWER>, const LOWER: usize, const UPPER: usize> Journal<T, LOWER,
         let meta = self.pull(0)?;
         let data = self.pull(1)?;
 
-        let idx = u64::from_le_bytes(unsafe { meta.align_to::<[u8; 8]>().1[0] });
-        let crc = u64::from_le_bytes(unsafe { meta.align_to::<[u8; 8]>().1[1] });
-
-        let mut digest = Digest::new(ISO);
-        digest.write(&idx.to_le_bytes());
-        digest.write(&data);
-        if digest.finish() == crc {
+        if let Some(idx) = target(&meta, &data) {
             self.push(idx + 2, &data)?;
         }
 
         Ok(())
     }
+
+    /// Whether the last write was interrupted, so that [Journal::replay]
+    /// would change the device
+    ///
+    /// Unlike [Journal::replay], this never writes.
+    #[inline]
+    pub fn is_dirty(&mut self) -> Result<bool, T::Error> {
+        let meta = self.pull(0)?;
+        let data = self.pull(1)?;
+
+        match target(&meta, &data) {
+            Some(idx) => Ok(self.pull(idx + 2)? != data),
+            None => Ok(false),
+        }
+    }
 }
 
 impl<T: Device<LOWER>, const LOWER: usize, const UPPER: usize> Device<UPPER>
@@ -93,31 +164,79 @@ impl<T: Device<LOWER>, const LOWER: usize, const UPPER: usize> Device<UPPER>
 
     #[inline]
     fn set(&mut self, index: u64, block: &[u8; UPPER]) -> Result<(), Self::Error> {
-        let mut digest = Digest::new(ISO);
-        digest.write(&index.to_le_bytes());
-        digest.write(block);
-        let crc = digest.finish();
-
-        let mut meta = [0u8; UPPER];
-        meta[..8].copy_from_slice(&index.to_le_bytes());
-        meta[8..][..8].copy_from_slice(&crc.to_le_bytes());
-
-        self.push(0, &meta)?;
+        self.push(0, &record(index, block))?;
         self.push(1, block)?;
         self.push(index + 2, block)?;
 
         Ok(())
     }
+
+    #[inline]
+    fn flush(&mut self) -> Result<(), Self::Error> {
+        self.device.flush()
+    }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
 
-    use quoin_memory::Memory;
+    use quoin_fault::{Fault, Plan};
+    use quoin_memory::{Memory, Sparse};
     use quoin_poweroff::Tear;
     use rand::Rng;
 
+    #[test]
+    fn crash() {
+        let old = [0x11u8; 1024];
+        let new = [0x22u8; 1024];
+
+        let mut base = Sparse::<512>::new(20);
+        let mut jrnl = Journal::<_, 512, 1024>::new(&mut base);
+        for i in 0..jrnl.len() {
+            jrnl.set(i, &old).unwrap();
+        }
+        let snapshot = base.snapshot();
+
+        // Lose power after each possible number of writes, on a fork of
+        // the same starting image.
+        let mut outcomes = Vec::new();
+        for writes in 0.. {
+            let mut fork = snapshot.fork();
+            let plan = Plan::new(|_| std::io::Error::from(std::io::ErrorKind::TimedOut));
+            plan.fail_after(writes);
+
+            let fault = Fault::new(&mut fork, plan);
+            let done = Journal::<_, 512, 1024>::new(fault).set(4, &new).is_ok();
+
+            // Only a journaled write that didn't reach its block is replayed.
+            let mut jrnl = Journal::<_, 512, 1024>::new(&mut fork);
+            assert_eq!(jrnl.is_dirty().unwrap(), (4..6).contains(&writes));
+            jrnl.replay().unwrap();
+            assert!(!jrnl.is_dirty().unwrap());
+            for i in (0..jrnl.len()).filter(|i| *i != 4) {
+                assert_eq!(jrnl.get(i).unwrap(), old);
+            }
+
+            let block = jrnl.get(4).unwrap();
+            assert!(
+                block == old || block == new,
+                "tearing after {} writes",
+                writes
+            );
+            outcomes.push(block == new);
+
+            if done {
+                break;
+            }
+        }
+
+        // Once both the record and the data are journaled (two blocks each),
+        // the write always survives.
+        assert_eq!(outcomes, [false, false, false, false, true, true, true]);
+        assert_eq!(snapshot.get((4 + 2) * 2).unwrap(), [0x11; 512]);
+    }
+
     #[test]
     fn tear() {
         let memory: Memory<512, 10> = Memory::default();
@@ -134,7 +253,7 @@ mod tests {
             // If our last block write failed.
             if i > 0 && !succ {
                 // Replay the journal
-                while let Err(..) = jrnl.replay() {}
+                while jrnl.replay().is_err() {}
 
                 // Test that *all* blocks have no tearing
                 for j in 0..jrnl.len() {
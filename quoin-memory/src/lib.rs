@@ -1,7 +1,20 @@
+mod sparse;
+
+pub use sparse::{Snapshot, Sparse};
+
+pub use quoin_device::OutOfBounds;
+
 use quoin_device::Device;
 
-use std::convert::Infallible;
+use std::convert::TryFrom;
 
+#[inline]
+fn check(index: u64, len: u64) -> Result<usize, OutOfBounds> {
+    OutOfBounds::check(index, len)?;
+    Ok(index as usize)
+}
+
+/// A device stored in an array, sized at compile time
 pub struct Memory<const SIZE: usize, const COUNT: usize>([[u8; SIZE]; COUNT]);
 
 impl<const SIZE: usize, const COUNT: usize> Default for Memory<SIZE, COUNT> {
@@ -11,7 +24,7 @@ impl<const SIZE: usize, const COUNT: usize> Default for Memory<SIZE, COUNT> {
 }
 
 impl<const SIZE: usize, const COUNT: usize> Device<SIZE> for Memory<SIZE, COUNT> {
-    type Error = Infallible;
+    type Error = OutOfBounds;
 
     #[inline]
     fn len(&self) -> u64 {
@@ -20,14 +33,175 @@ impl<const SIZE: usize, const COUNT: usize> Device<SIZE> for Memory<SIZE, COUNT>
 
     #[inline]
     fn get(&mut self, index: u64) -> Result<[u8; SIZE], Self::Error> {
-        assert!(index <= COUNT as u64);
-        Ok(self.0[index as usize])
+        Ok(self.0[check(index, self.len())?])
+    }
+
+    #[inline]
+    fn set(&mut self, index: u64, block: &[u8; SIZE]) -> Result<(), Self::Error> {
+        let index = check(index, self.len())?;
+        self.0[index].copy_from_slice(block);
+        Ok(())
+    }
+}
+
+/// A device stored on the heap, sized at run time
+///
+/// The blocks are allocated zeroed, so the operating system only commits
+/// the pages that are written. This makes large simulated disks cheap.
+pub struct Heap<const SIZE: usize>(Vec<u8>);
+
+impl<const SIZE: usize> Heap<SIZE> {
+    /// Creates a zeroed device of `blocks` blocks
+    ///
+    /// Like [vec!], this panics if the size in bytes overflows `usize` and
+    /// aborts if the allocation fails.
+    pub fn new(blocks: u64) -> Self {
+        let size = blocks.checked_mul(SIZE as u64);
+        let size = size.and_then(|size| usize::try_from(size).ok());
+        Self(vec![0; size.expect("capacity overflow")])
+    }
+
+    #[inline]
+    fn block(&self, index: u64) -> Result<std::ops::Range<usize>, OutOfBounds> {
+        let start = check(index, self.len())? * SIZE;
+        Ok(start..start + SIZE)
+    }
+}
+
+impl<const SIZE: usize> Device<SIZE> for Heap<SIZE> {
+    type Error = OutOfBounds;
+
+    #[inline]
+    fn len(&self) -> u64 {
+        (self.0.len() / SIZE) as u64
+    }
+
+    #[inline]
+    fn get(&mut self, index: u64) -> Result<[u8; SIZE], Self::Error> {
+        let mut block = [0; SIZE];
+        block.copy_from_slice(&self.0[self.block(index)?]);
+        Ok(block)
     }
 
     #[inline]
     fn set(&mut self, index: u64, block: &[u8; SIZE]) -> Result<(), Self::Error> {
-        assert!(index <= COUNT as u64);
-        self.0[index as usize].copy_from_slice(block);
+        let range = self.block(index)?;
+        self.0[range].copy_from_slice(block);
         Ok(())
     }
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    use quoin_device::{Error as _, ErrorKind};
+
+    #[test]
+    fn memory() {
+        let mut memory: Memory<512, 4> = Memory::default();
+        memory.set(3, &[0xaa; 512]).unwrap();
+        assert_eq!(memory.get(3).unwrap(), [0xaa; 512]);
+
+        let err = OutOfBounds { index: 4, len: 4 };
+        assert_eq!(memory.get(4).unwrap_err(), err);
+        assert_eq!(memory.set(4, &[0; 512]).unwrap_err(), err);
+    }
+
+    #[test]
+    fn heap() {
+        // Far too large for the stack, but only two blocks are touched.
+        let mut heap = Heap::<4096>::new(1 << 20);
+        assert_eq!(heap.len(), 1 << 20);
+        assert_eq!(heap.get(12345).unwrap(), [0; 4096]);
+
+        heap.set((1 << 20) - 1, &[0x55; 4096]).unwrap();
+        assert_eq!(heap.get((1 << 20) - 1).unwrap(), [0x55; 4096]);
+
+        let err = heap.get(1 << 20).unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "block 1048576 is out of bounds (1048576 blocks)"
+        );
+        assert_eq!(err.kind(), ErrorKind::Other);
+    }
+
+    #[test]
+    #[should_panic(expected = "capacity overflow")]
+    fn heap_overflow() {
+        Heap::<4096>::new(u64::MAX);
+    }
+
+    #[test]
+    fn sparse() {
+        // A 16 TiB disk
+        let mut sparse = Sparse::<4096>::new(1 << 32);
+        assert_eq!(sparse.len(), 1 << 32);
+        assert_eq!(sparse.resident(), 0);
+        assert_eq!(sparse.get((1 << 32) - 1).unwrap(), [0; 4096]);
+        assert_eq!(sparse.resident(), 0);
+
+        for index in [0, 7, 8, 9, (1 << 32) - 1] {
+            sparse.set(index, &[index as u8 | 1; 4096]).unwrap();
+        }
+        sparse.set(8, &[0xaa; 4096]).unwrap();
+        assert_eq!(sparse.resident(), 5);
+        assert_eq!(sparse.resident_bytes(), 5 * 4096);
+        assert_eq!(sparse.get(8).unwrap(), [0xaa; 4096]);
+        assert_eq!(sparse.get(9).unwrap(), [9; 4096]);
+
+        sparse.discard(8..10).unwrap();
+        assert_eq!(sparse.resident(), 3);
+        assert!(sparse.is_resident(7));
+        assert!(!sparse.is_resident(8));
+        assert_eq!(sparse.get(8).unwrap(), [0; 4096]);
+        assert_eq!(sparse.get(7).unwrap(), [7; 4096]);
+
+        let err = sparse.discard(0..(1 << 32) + 1).unwrap_err();
+        assert_eq!(err.index, 1 << 32);
+        assert_eq!(sparse.resident(), 3);
+        assert!(sparse.set(1 << 32, &[0; 4096]).is_err());
+
+        sparse.discard(0..1 << 32).unwrap();
+        assert_eq!(sparse.resident(), 0);
+    }
+
+    #[test]
+    fn snapshot() {
+        let mut base = Sparse::<512>::new(1 << 40);
+        base.set(1, &[1; 512]).unwrap();
+        base.set(2, &[2; 512]).unwrap();
+
+        let snapshot = base.snapshot();
+        base.set(1, &[0xff; 512]).unwrap();
+        base.discard(2..3).unwrap();
+        assert_eq!(snapshot.len(), 1 << 40);
+        assert_eq!(snapshot.get(1).unwrap(), [1; 512]);
+        assert_eq!(snapshot.get(2).unwrap(), [2; 512]);
+
+        // Forks diverge from the snapshot and from each other.
+        let mut forks: Vec<_> = (0..3).map(|_| snapshot.fork()).collect();
+        for (i, fork) in forks.iter_mut().enumerate() {
+            fork.set(2, &[i as u8 + 10; 512]).unwrap();
+            fork.set(1 << 39, &[i as u8 + 20; 512]).unwrap();
+        }
+
+        for (i, fork) in forks.iter_mut().enumerate() {
+            assert_eq!(fork.get(1).unwrap(), [1; 512]);
+            assert_eq!(fork.get(2).unwrap(), [i as u8 + 10; 512]);
+            assert_eq!(fork.get(1 << 39).unwrap(), [i as u8 + 20; 512]);
+            assert_eq!(fork.resident(), 3);
+        }
+
+        let mut branch = forks[0].fork();
+        branch.set(1, &[0x55; 512]).unwrap();
+        assert_eq!(forks[0].get(1).unwrap(), [1; 512]);
+        assert_eq!(branch.get(2).unwrap(), [10; 512]);
+
+        assert_eq!(base.get(1).unwrap(), [0xff; 512]);
+        assert_eq!(base.get(2).unwrap(), [0; 512]);
+        assert_eq!(base.get(1 << 39).unwrap(), [0; 512]);
+        assert_eq!(snapshot.get(1 << 39).unwrap(), [0; 512]);
+        assert!(snapshot.get(1 << 40).is_err());
+    }
+}
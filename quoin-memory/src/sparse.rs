@@ -0,0 +1,150 @@
+use super::{check, OutOfBounds};
+
+use quoin_device::Device;
+
+use std::collections::BTreeMap;
+use std::ops::Range;
+use std::sync::Arc;
+
+// Both the map and the blocks are shared between snapshots and forks, and
+// copied only when modified.
+type Blocks<const SIZE: usize> = Arc<BTreeMap<u64, Arc<[u8; SIZE]>>>;
+
+/// A device that stores only the blocks written to it
+///
+/// Unwritten blocks read as zeros, so a simulated disk of many terabytes
+/// costs only as much memory as the blocks actually in use.
+///
+/// A device can be captured with [Sparse::snapshot] or branched with
+/// [Sparse::fork] in constant time. Unmodified blocks remain shared, so
+/// many divergent histories can be explored from the same starting image.
+#[derive(Clone)]
+pub struct Sparse<const SIZE: usize> {
+    blocks: Blocks<SIZE>,
+    len: u64,
+}
+
+impl<const SIZE: usize> Sparse<SIZE> {
+    /// Creates an empty device of `len` blocks
+    pub fn new(len: u64) -> Self {
+        Self {
+            blocks: Arc::default(),
+            len,
+        }
+    }
+
+    /// The number of blocks held in memory
+    ///
+    /// Blocks shared with snapshots and forks are included.
+    #[inline]
+    pub fn resident(&self) -> u64 {
+        self.blocks.len() as u64
+    }
+
+    /// The number of bytes of block data held in memory
+    #[inline]
+    pub fn resident_bytes(&self) -> u64 {
+        self.resident() * SIZE as u64
+    }
+
+    /// Whether the block at `index` is held in memory
+    #[inline]
+    pub fn is_resident(&self, index: u64) -> bool {
+        self.blocks.contains_key(&index)
+    }
+
+    /// Drops the given blocks, which then read as zeros
+    pub fn discard(&mut self, blocks: Range<u64>) -> Result<(), OutOfBounds> {
+        if blocks.end > self.len {
+            check(blocks.end - 1, self.len)?;
+        }
+
+        let map = Arc::make_mut(&mut self.blocks);
+        let mut tail = map.split_off(&blocks.start);
+        let mut rest = tail.split_off(&blocks.end);
+        map.append(&mut rest);
+        Ok(())
+    }
+
+    #[inline]
+    fn read(&self, index: u64) -> Result<[u8; SIZE], OutOfBounds> {
+        check(index, self.len)?;
+
+        match self.blocks.get(&index) {
+            Some(block) => Ok(**block),
+            None => Ok([0; SIZE]),
+        }
+    }
+
+    /// Captures the current contents of the device
+    ///
+    /// Later writes to the device do not affect the snapshot.
+    #[inline]
+    pub fn snapshot(&self) -> Snapshot<SIZE> {
+        Snapshot(self.clone())
+    }
+
+    /// Creates an independent, writable copy of the device
+    #[inline]
+    pub fn fork(&self) -> Self {
+        self.clone()
+    }
+}
+
+impl<const SIZE: usize> Device<SIZE> for Sparse<SIZE> {
+    type Error = OutOfBounds;
+
+    #[inline]
+    fn len(&self) -> u64 {
+        self.len
+    }
+
+    #[inline]
+    fn get(&mut self, index: u64) -> Result<[u8; SIZE], Self::Error> {
+        self.read(index)
+    }
+
+    #[inline]
+    fn set(&mut self, index: u64, block: &[u8; SIZE]) -> Result<(), Self::Error> {
+        check(index, self.len)?;
+
+        let map = Arc::make_mut(&mut self.blocks);
+        match map.get_mut(&index).and_then(Arc::get_mut) {
+            Some(stored) => stored.copy_from_slice(block),
+            None => {
+                map.insert(index, Arc::new(*block));
+            }
+        }
+
+        Ok(())
+    }
+}
+
+/// A frozen copy of a [Sparse] device
+#[derive(Clone)]
+pub struct Snapshot<const SIZE: usize>(Sparse<SIZE>);
+
+impl<const SIZE: usize> Snapshot<SIZE> {
+    /// The number of blocks in the snapshot
+    #[inline]
+    pub fn len(&self) -> u64 {
+        self.0.len
+    }
+
+    #[inline]
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Reads a block from the snapshot
+    #[inline]
+    pub fn get(&self, index: u64) -> Result<[u8; SIZE], OutOfBounds> {
+        self.0.read(index)
+    }
+
+    /// Creates a writable device starting from the snapshot
+    #[inline]
+    pub fn fork(&self) -> Sparse<SIZE> {
+        self.0.clone()
+    }
+}
@@ -1,22 +1,205 @@
-use quoin_device::Device;
+use quoin_device::{Device, OutOfBounds};
 
-pub struct Null(());
+/// A device that reads as zeros and discards writes
+#[derive(Copy, Clone, Debug)]
+pub struct Zero {
+    len: u64,
+}
+
+impl Zero {
+    /// Creates a device of `len` blocks
+    pub fn new(len: u64) -> Self {
+        Self { len }
+    }
+}
+
+impl<const SIZE: usize> Device<SIZE> for Zero {
+    type Error = OutOfBounds;
+
+    #[inline]
+    fn len(&self) -> u64 {
+        self.len
+    }
+
+    #[inline]
+    fn get(&mut self, index: u64) -> Result<[u8; SIZE], Self::Error> {
+        OutOfBounds::check(index, self.len)?;
+        Ok([0; SIZE])
+    }
+
+    #[inline]
+    fn set(&mut self, index: u64, _block: &[u8; SIZE]) -> Result<(), Self::Error> {
+        OutOfBounds::check(index, self.len)
+    }
+}
+
+/// A device that reads as a fixed pattern and discards writes
+#[derive(Clone, Debug)]
+pub struct Pattern {
+    len: u64,
+    bytes: Option<Vec<u8>>,
+}
+
+impl Pattern {
+    /// Creates a device of `len` blocks, each filled with `bytes` repeated
+    pub fn repeat(len: u64, bytes: &[u8]) -> Self {
+        assert!(!bytes.is_empty());
+
+        Self {
+            len,
+            bytes: Some(bytes.into()),
+        }
+    }
+
+    /// Creates a device of `len` blocks, each filled with its own index
+    ///
+    /// The index is repeated as a little-endian `u64` (any remainder is
+    /// filled with a truncated copy). Every 8-byte word therefore names the
+    /// block it came from, which shows where a layer maps its blocks.
+    pub fn stamped(len: u64) -> Self {
+        Self { len, bytes: None }
+    }
+
+    /// The contents of the block at `index`
+    pub fn block<const SIZE: usize>(&self, index: u64) -> [u8; SIZE] {
+        let stamp = index.to_le_bytes();
+        let bytes = match self.bytes {
+            Some(ref bytes) => &bytes[..],
+            None => &stamp[..],
+        };
+
+        let mut block = [0; SIZE];
+        for (dst, src) in block.iter_mut().zip(bytes.iter().cycle()) {
+            *dst = *src;
+        }
+
+        block
+    }
+}
+
+impl<const SIZE: usize> Device<SIZE> for Pattern {
+    type Error = OutOfBounds;
+
+    #[inline]
+    fn len(&self) -> u64 {
+        self.len
+    }
 
-impl<const SIZE: usize> Device<SIZE> for Null {
-    type Error = std::convert::Infallible;
+    #[inline]
+    fn get(&mut self, index: u64) -> Result<[u8; SIZE], Self::Error> {
+        OutOfBounds::check(index, self.len)?;
+        Ok(self.block(index))
+    }
+
+    #[inline]
+    fn set(&mut self, index: u64, _block: &[u8; SIZE]) -> Result<(), Self::Error> {
+        OutOfBounds::check(index, self.len)
+    }
+}
+
+/// A device that reads as zeros and counts the writes it discards
+#[derive(Copy, Clone, Debug)]
+pub struct Sink {
+    len: u64,
+    writes: u64,
+    flushes: u64,
+}
+
+impl Sink {
+    /// Creates a device of `len` blocks
+    pub fn new(len: u64) -> Self {
+        Self {
+            len,
+            writes: 0,
+            flushes: 0,
+        }
+    }
+
+    /// The number of blocks written
+    #[inline]
+    pub fn writes(&self) -> u64 {
+        self.writes
+    }
+
+    /// The number of flushes
+    #[inline]
+    pub fn flushes(&self) -> u64 {
+        self.flushes
+    }
+}
+
+impl<const SIZE: usize> Device<SIZE> for Sink {
+    type Error = OutOfBounds;
 
     #[inline]
     fn len(&self) -> u64 {
-        0
+        self.len
     }
 
     #[inline]
-    fn get(&mut self, _index: u64) -> Result<[u8; SIZE], Self::Error> {
+    fn get(&mut self, index: u64) -> Result<[u8; SIZE], Self::Error> {
+        OutOfBounds::check(index, self.len)?;
         Ok([0; SIZE])
     }
 
     #[inline]
-    fn set(&mut self, _index: u64, _block: &[u8; SIZE]) -> Result<(), Self::Error> {
+    fn set(&mut self, index: u64, _block: &[u8; SIZE]) -> Result<(), Self::Error> {
+        OutOfBounds::check(index, self.len)?;
+        self.writes += 1;
+        Ok(())
+    }
+
+    #[inline]
+    fn flush(&mut self) -> Result<(), Self::Error> {
+        self.flushes += 1;
         Ok(())
     }
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn zero() {
+        let mut zero = Zero::new(8);
+        assert_eq!(Device::<512>::len(&zero), 8);
+
+        zero.set(7, &[0xff; 512]).unwrap();
+        assert_eq!(zero.get(7).unwrap(), [0; 512]);
+
+        let err = Device::<512>::get(&mut zero, 8).unwrap_err();
+        assert_eq!(err, OutOfBounds { index: 8, len: 8 });
+        assert!(zero.set(8, &[0; 512]).is_err());
+    }
+
+    #[test]
+    fn pattern() {
+        let mut pattern = Pattern::repeat(4, b"abc");
+        assert_eq!(&pattern.get(3).unwrap(), b"abcabcab");
+        pattern.set(3, b"xxxxxxxx").unwrap();
+        assert_eq!(&pattern.get(3).unwrap(), b"abcabcab");
+        assert!(Device::<8>::get(&mut pattern, 4).is_err());
+
+        let mut stamped = Pattern::stamped(1 << 40);
+        let block: [u8; 20] = stamped.get(0x0102).unwrap();
+        assert_eq!(block[..8], 0x0102u64.to_le_bytes());
+        assert_eq!(block[8..16], 0x0102u64.to_le_bytes());
+        assert_eq!(block[16..], [0x02, 0x01, 0, 0]);
+        assert_eq!(stamped.block::<20>(0x0102), block);
+    }
+
+    #[test]
+    fn sink() {
+        let mut sink = Sink::new(8);
+        for i in 0..8 {
+            sink.set(i, &[0xff; 512]).unwrap();
+        }
+        assert!(sink.set(8, &[0xff; 512]).is_err());
+        Device::<512>::flush(&mut sink).unwrap();
+
+        assert_eq!(sink.writes(), 8);
+        assert_eq!(sink.flushes(), 1);
+        assert_eq!(sink.get(0).unwrap(), [0; 512]);
+    }
+}
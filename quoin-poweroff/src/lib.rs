@@ -1,10 +1,15 @@
-use quoin_device::Device;
+use quoin_device::{Device, ErrorKind};
+
+use std::fmt::{Display, Formatter};
 
 use rand::Rng;
 
 #[derive(Copy, Clone, Debug)]
 pub enum Error<T> {
+    /// The underlying device failed
     Parent(T),
+
+    /// The block was only partially written
     Torn,
 }
 
@@ -15,6 +20,33 @@ impl<T> From<T> for Error<T> {
     }
 }
 
+impl<T> Display for Error<T> {
+    fn fmt(&self, f: &mut Formatter) -> std::fmt::Result {
+        f.write_str(match self {
+            Self::Parent(..) => "device error",
+            Self::Torn => "the block write was torn",
+        })
+    }
+}
+
+impl<T: std::error::Error + 'static> std::error::Error for Error<T> {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Self::Parent(e) => Some(e),
+            Self::Torn => None,
+        }
+    }
+}
+
+impl<T: quoin_device::Error> quoin_device::Error for Error<T> {
+    fn kind(&self) -> ErrorKind {
+        match self {
+            Self::Parent(e) => e.kind(),
+            Self::Torn => ErrorKind::Tear,
+        }
+    }
+}
+
 // Simulates tearing during a block write
 pub struct Tear<T: Device<SIZE>, const SIZE: usize> {
     device: T,
@@ -62,6 +94,11 @@ impl<T: Device<SIZE>, const SIZE: usize> Device<SIZE> for Tear<T, SIZE> {
         self.device.set(index, block)?;
         Err(Error::Torn)
     }
+
+    #[inline]
+    fn flush(&mut self) -> Result<(), Self::Error> {
+        Ok(self.device.flush()?)
+    }
 }
 
 #[cfg(test)]
@@ -80,7 +117,8 @@ mod test {
         let mut torn = 0;
 
         for _ in 0..TOTAL {
-            if tear.set(0, &BLOCK).is_err() {
+            if let Err(e) = tear.set(0, &BLOCK) {
+                assert_eq!(quoin_device::Error::kind(&e), ErrorKind::Tear);
                 torn += 1;
             }
         }
@@ -0,0 +1,125 @@
+use super::Error;
+
+use std::path::PathBuf;
+
+use serde::{Deserialize, Serialize};
+
+/// A description of a device stack, from the bottom layer up
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Config {
+    #[serde(rename = "layer")]
+    pub layers: Vec<Layer>,
+}
+
+impl Config {
+    pub fn from_toml(config: &str) -> Result<Self, Error> {
+        toml::from_str(config).map_err(|e| Error::Config(e.to_string()))
+    }
+
+    pub fn from_json(config: &str) -> Result<Self, Error> {
+        serde_json::from_str(config).map_err(|e| Error::Config(e.to_string()))
+    }
+}
+
+/// A single layer of a device stack
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "kebab-case")]
+pub enum Layer {
+    /// A file or block device; this must be the bottom layer
+    #[serde(rename_all = "kebab-case")]
+    File { path: PathBuf, block_size: usize },
+
+    /// A GPT partition, selected by number or by name
+    Partition {
+        number: Option<u32>,
+        label: Option<String>,
+    },
+
+    /// A journal that prevents torn writes of its (larger) blocks
+    #[serde(rename_all = "kebab-case")]
+    Journal { block_size: usize },
+
+    /// Authenticates each block, which shrinks the block size by the tag
+    Hmac { hash: Hash, key: Key },
+
+    /// Encrypts each block
+    Crypt { cipher: Cipher, key: Key },
+}
+
+impl Layer {
+    pub fn name(&self) -> &'static str {
+        match self {
+            Self::File { .. } => "file",
+            Self::Partition { .. } => "partition",
+            Self::Journal { .. } => "journal",
+            Self::Hmac { .. } => "hmac",
+            Self::Crypt { .. } => "crypt",
+        }
+    }
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum Hash {
+    Sha256,
+    Sha384,
+    Sha512,
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Cipher {
+    #[serde(rename = "aes-128-xts")]
+    Aes128Xts,
+
+    #[serde(rename = "aes-256-xts")]
+    Aes256Xts,
+}
+
+impl Cipher {
+    pub(crate) fn cipher(self) -> openssl::symm::Cipher {
+        match self {
+            Self::Aes128Xts => openssl::symm::Cipher::aes_128_xts(),
+            Self::Aes256Xts => openssl::symm::Cipher::aes_256_xts(),
+        }
+    }
+}
+
+/// Where to find the key for a layer
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum Key {
+    /// The key, hex-encoded
+    Hex(String),
+
+    /// A file containing the raw key
+    File(PathBuf),
+
+    /// An environment variable containing the hex-encoded key
+    Env(String),
+}
+
+impl Key {
+    pub fn load(&self) -> Result<Vec<u8>, Error> {
+        match self {
+            Self::Hex(hex) => decode(hex),
+            Self::File(path) => Ok(std::fs::read(path)?),
+            Self::Env(name) => match std::env::var(name) {
+                Ok(hex) => decode(&hex),
+                Err(..) => Err(Error::Config(format!("{} is not set", name))),
+            },
+        }
+    }
+}
+
+fn decode(hex: &str) -> Result<Vec<u8>, Error> {
+    let hex = hex.trim();
+    if !hex.len().is_multiple_of(2) || !hex.is_ascii() {
+        return Err(Error::Config("invalid hex key".into()));
+    }
+
+    (0..hex.len())
+        .step_by(2)
+        .map(|i| u8::from_str_radix(&hex[i..i + 2], 16))
+        .collect::<Result<_, _>>()
+        .map_err(|_| Error::Config("invalid hex key".into()))
+}
@@ -0,0 +1,560 @@
+mod config;
+
+pub use config::{Cipher, Config, Hash, Key, Layer};
+
+use std::fmt::{Display, Formatter};
+use std::path::Path;
+
+use quoin_device::{Dyn, DynDevice, DynError, ErrorKind, Fixed};
+use quoin_encryption::Crypt;
+use quoin_file::{File, OpenOptions};
+use quoin_gpt::Disk;
+use quoin_integrity::{DynHmac, Hmac, Sha256, Sha512};
+use quoin_journal::Journal;
+
+#[derive(Debug)]
+pub enum Error {
+    /// The stack is not configured correctly
+    Config(String),
+
+    /// The stack could not be opened
+    Io(std::io::Error),
+
+    /// A layer failed
+    Layer(DynError),
+
+    /// The block index or size is invalid
+    OutOfBounds,
+}
+
+impl Error {
+    fn layer<E: quoin_device::Error>(error: E) -> Self {
+        Self::Layer(DynError::new(error))
+    }
+}
+
+impl From<std::io::Error> for Error {
+    fn from(value: std::io::Error) -> Self {
+        Self::Io(value)
+    }
+}
+
+impl Display for Error {
+    fn fmt(&self, f: &mut Formatter) -> std::fmt::Result {
+        match self {
+            Self::Config(msg) => write!(f, "invalid configuration: {}", msg),
+            Self::Io(e) => write!(f, "{}", e),
+            Self::Layer(e) => write!(f, "layer error: {}", e),
+            Self::OutOfBounds => write!(f, "out of bounds"),
+        }
+    }
+}
+
+impl std::error::Error for Error {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Self::Io(e) => Some(e),
+            Self::Layer(e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+impl quoin_device::Error for Error {
+    fn kind(&self) -> ErrorKind {
+        match self {
+            Self::Io(..) => ErrorKind::Io,
+            Self::Layer(e) => e.kind(),
+            _ => ErrorKind::Other,
+        }
+    }
+}
+
+// Instantiates `$body` with the constant `$S` for each supported block size.
+macro_rules! sizes {
+    ($size:expr, $S:ident => $body:expr; $($n:literal)+) => {
+        match $size {
+            $($n => {
+                const $S: usize = $n;
+                $body
+            })+
+            size => Err(Error::Config(format!("unsupported block size: {}", size))),
+        }
+    };
+}
+
+type Volume = Box<dyn DynDevice>;
+
+// Gives a volume back its static block size, so that it can be layered.
+fn fixed<const SIZE: usize>(volume: Volume) -> Result<Fixed<Volume, SIZE>, Error> {
+    Fixed::new(volume).map_err(|volume| {
+        Error::Config(format!(
+            "expected a block size of {}, not {}",
+            SIZE,
+            volume.block_size()
+        ))
+    })
+}
+
+fn file(path: &Path, block_size: usize, read_only: bool) -> Result<Volume, Error> {
+    sizes!(block_size, S => {
+        let file: File<S> = OpenOptions::new().read_only(read_only).open(path)?;
+        Ok(Box::new(Dyn(file)) as Volume)
+    }; 512 1024 2048 4096)
+}
+
+fn partition(volume: Volume, number: Option<u32>, label: Option<&str>) -> Result<Volume, Error> {
+    sizes!(volume.block_size(), S => {
+        let disk = Disk::load(fixed::<S>(volume)?).map_err(Error::layer)?;
+        let disk = disk.ok_or_else(|| Error::Config("no partition table".into()))?;
+
+        let part = match (number, label) {
+            (Some(number), None) => disk.partition(number),
+            (None, Some(label)) => disk.find_by_label(label),
+            _ => return Err(Error::Config("partitions need a number or a label".into())),
+        };
+
+        let part = part.ok_or_else(|| Error::Config("no such partition".into()))?;
+        Ok(Box::new(Dyn(part)) as Volume)
+    }; 512 1024 2048 4096)
+}
+
+fn journal(volume: Volume, block_size: usize, read_only: bool) -> Result<Volume, Error> {
+    if !block_size.is_multiple_of(volume.block_size()) {
+        return Err(Error::Config(
+            "journal blocks must span whole blocks".into(),
+        ));
+    }
+
+    sizes!(volume.block_size(), L => sizes!(block_size, U => {
+        let mut journal = Journal::<_, L, U>::new(fixed::<L>(volume)?);
+
+        // Without replaying, an interrupted write could be read torn.
+        match read_only {
+            false => journal.replay().map_err(Error::Layer)?,
+            true if journal.is_dirty().map_err(Error::Layer)? => {
+                return Err(Error::Config("the journal must be replayed first".into()))
+            }
+            true => (),
+        }
+
+        Ok(Box::new(Dyn(journal)) as Volume)
+    }; 512 1024 2048 4096); 512 1024 2048 4096)
+}
+
+fn hmac(volume: Volume, hash: Hash, key: Vec<u8>) -> Result<Volume, Error> {
+    let len = match hash {
+        Hash::Sha256 => 32,
+        Hash::Sha384 => 48,
+        Hash::Sha512 => 64,
+    };
+
+    if key.len() != len {
+        return Err(Error::Config(format!("hmac keys must be {} bytes", len)));
+    }
+
+    // The tag must evenly divide the block, so SHA-384 is never usable.
+    macro_rules! hmac {
+        ($($size:literal)+) => {
+            match (volume.block_size(), hash) {
+                $(
+                    ($size, Hash::Sha256) => {
+                        let device = fixed::<$size>(volume)?;
+                        let hmac = Hmac::new(device, &key, Sha256).map_err(Error::layer)?;
+                        Ok(Box::new(DynHmac(hmac)) as Volume)
+                    }
+
+                    ($size, Hash::Sha512) => {
+                        let device = fixed::<$size>(volume)?;
+                        let hmac = Hmac::new(device, &key, Sha512).map_err(Error::layer)?;
+                        Ok(Box::new(DynHmac(hmac)) as Volume)
+                    }
+                )+
+
+                (size, hash) => Err(Error::Config(format!(
+                    "{:?} is not supported with a block size of {}",
+                    hash, size
+                ))),
+            }
+        };
+    }
+
+    hmac!(512 1024 2048 4096)
+}
+
+fn crypt(volume: Volume, cipher: Cipher, key: Vec<u8>) -> Result<Volume, Error> {
+    let cipher = cipher.cipher();
+    if key.len() != cipher.key_len() {
+        let msg = format!("crypt keys must be {} bytes", cipher.key_len());
+        return Err(Error::Config(msg));
+    }
+
+    sizes!(volume.block_size(), S => {
+        let crypt = Crypt::new(fixed::<S>(volume)?, key, cipher).map_err(Error::layer)?;
+        Ok(Box::new(Dyn(crypt)) as Volume)
+    }; 448 480 512 960 992 1024 1984 2016 2048 4032 4064 4096)
+}
+
+/// A summary of one layer of a [Stack]
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Level {
+    pub name: &'static str,
+    pub block_size: usize,
+    pub len: u64,
+}
+
+/// A device stack built at runtime from a [Config]
+///
+/// Errors from [DynDevice] methods wrap an [Error].
+pub struct Stack {
+    volume: Volume,
+    levels: Vec<Level>,
+}
+
+impl Stack {
+    /// Opens the stack for reading and writing, replaying any journals
+    pub fn open(config: &Config) -> Result<Self, Error> {
+        Self::build(config, false)
+    }
+
+    /// Opens the stack without ever writing to it
+    ///
+    /// The file is locked shared rather than exclusively, and writes fail.
+    /// Since journals can't be replayed, this fails if one needs to be.
+    pub fn open_read_only(config: &Config) -> Result<Self, Error> {
+        Self::build(config, true)
+    }
+
+    fn build(config: &Config, read_only: bool) -> Result<Self, Error> {
+        let mut layers = config.layers.iter();
+
+        let mut volume = match layers.next() {
+            Some(Layer::File { path, block_size }) => file(path, *block_size, read_only)?,
+            _ => return Err(Error::Config("the bottom layer must be a file".into())),
+        };
+
+        let mut levels = vec![Level {
+            name: "file",
+            block_size: volume.block_size(),
+            len: volume.len(),
+        }];
+
+        for layer in layers {
+            volume = match layer {
+                Layer::File { .. } => {
+                    return Err(Error::Config("only the bottom layer can be a file".into()))
+                }
+
+                Layer::Partition { number, label } => partition(volume, *number, label.as_deref())?,
+
+                Layer::Journal { block_size } => journal(volume, *block_size, read_only)?,
+                Layer::Hmac { hash, key } => hmac(volume, *hash, key.load()?)?,
+                Layer::Crypt { cipher, key } => crypt(volume, *cipher, key.load()?)?,
+            };
+
+            levels.push(Level {
+                name: layer.name(),
+                block_size: volume.block_size(),
+                len: volume.len(),
+            });
+        }
+
+        Ok(Self { volume, levels })
+    }
+
+    /// Describes each layer of the stack, from the bottom up
+    pub fn levels(&self) -> &[Level] {
+        &self.levels
+    }
+}
+
+impl Stack {
+    fn check(&self, index: u64, block: &[u8]) -> Result<(), DynError> {
+        if index >= self.len() || block.len() != self.block_size() {
+            return Err(DynError::new(Error::OutOfBounds));
+        }
+
+        Ok(())
+    }
+}
+
+impl DynDevice for Stack {
+    #[inline]
+    fn block_size(&self) -> usize {
+        self.volume.block_size()
+    }
+
+    #[inline]
+    fn len(&self) -> u64 {
+        self.volume.len()
+    }
+
+    #[inline]
+    fn get(&mut self, index: u64, block: &mut [u8]) -> Result<(), DynError> {
+        self.check(index, block)?;
+        self.volume.get(index, block)
+    }
+
+    #[inline]
+    fn set(&mut self, index: u64, block: &[u8]) -> Result<(), DynError> {
+        self.check(index, block)?;
+        self.volume.set(index, block)
+    }
+
+    #[inline]
+    fn flush(&mut self) -> Result<(), DynError> {
+        self.volume.flush()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    use std::convert::TryFrom;
+
+    use quoin_device::{Device, Error as _};
+    use quoin_gpt::Attributes;
+
+    const KEY: &str = "000102030405060708090a0b0c0d0e0f101112131415161718191a1b1c1d1e1f\
+                       202122232425262728292a2b2c2d2e2f303132333435363738393a3b3c3d3e3f";
+
+    fn image(blocks: u64) -> tempfile::NamedTempFile {
+        let file = tempfile::NamedTempFile::new().unwrap();
+        file.as_file().set_len(blocks * 512).unwrap();
+        file
+    }
+
+    #[test]
+    fn config() {
+        let toml = format!(
+            r#"
+            [[layer]]
+            type = "file"
+            path = "/dev/sda"
+            block-size = 512
+
+            [[layer]]
+            type = "partition"
+            label = "data"
+
+            [[layer]]
+            type = "crypt"
+            cipher = "aes-256-xts"
+            key = {{ hex = "{}" }}
+            "#,
+            KEY
+        );
+
+        let json = format!(
+            r#"{{"layer": [
+                {{"type": "file", "path": "/dev/sda", "block-size": 512}},
+                {{"type": "partition", "label": "data"}},
+                {{"type": "crypt", "cipher": "aes-256-xts", "key": {{"hex": "{}"}}}}
+            ]}}"#,
+            KEY
+        );
+
+        let config = Config::from_toml(&toml).unwrap();
+        assert_eq!(Config::from_json(&json).unwrap(), config);
+        assert_eq!(config.layers[1].name(), "partition");
+        assert!(Config::from_toml("[[layer]]\ntype = \"floppy\"").is_err());
+    }
+
+    #[test]
+    fn stack() {
+        let file = image(64);
+        let toml = format!(
+            r#"
+            [[layer]]
+            type = "file"
+            path = "{}"
+            block-size = 512
+
+            [[layer]]
+            type = "journal"
+            block-size = 4096
+
+            [[layer]]
+            type = "hmac"
+            hash = "sha256"
+            key = {{ hex = "{}" }}
+
+            [[layer]]
+            type = "crypt"
+            cipher = "aes-256-xts"
+            key = {{ hex = "{}" }}
+            "#,
+            file.path().display(),
+            &KEY[..64],
+            KEY
+        );
+
+        let config = Config::from_toml(&toml).unwrap();
+        let mut stack = Stack::open(&config).unwrap();
+
+        let sizes: Vec<_> = stack.levels().iter().map(|l| l.block_size).collect();
+        assert_eq!(sizes, vec![512, 4096, 4064, 4064]);
+        assert_eq!(stack.len(), 6);
+
+        stack.set(3, &[0xaa; 4064]).unwrap();
+        assert_eq!(
+            stack.set(3, &[0xaa; 512]).unwrap_err().to_string(),
+            "out of bounds"
+        );
+        drop(stack);
+
+        let mut stack = Stack::open(&config).unwrap();
+        let mut block = [0u8; 4064];
+        stack.get(3, &mut block).unwrap();
+        assert_eq!(block[..], [0xaa; 4064][..]);
+
+        // The data is neither stored in the clear nor accepted if modified.
+        let mut raw = File::<512>::try_from(file.reopen().unwrap()).unwrap();
+        let mut data = raw.get(5 * 8).unwrap();
+        assert_ne!(data, [0xaa; 512]);
+        data[0] ^= 1;
+        raw.set(5 * 8, &data).unwrap();
+
+        let err = stack.get(3, &mut block).unwrap_err();
+        assert_eq!(err.kind(), ErrorKind::Integrity);
+    }
+
+    #[test]
+    fn read_only() {
+        let file = image(64);
+        let config = Config {
+            layers: vec![
+                Layer::File {
+                    path: file.path().into(),
+                    block_size: 512,
+                },
+                Layer::Journal { block_size: 4096 },
+            ],
+        };
+
+        let mut stack = Stack::open(&config).unwrap();
+        stack.set(3, &[0xaa; 4096]).unwrap();
+        drop(stack);
+
+        // Nothing is written, not even by opening the stack.
+        let before = std::fs::read(file.path()).unwrap();
+        let mut stack = Stack::open_read_only(&config).unwrap();
+        let mut block = [0u8; 4096];
+        stack.get(3, &mut block).unwrap();
+        assert_eq!(block[..], [0xaa; 4096][..]);
+        assert!(stack.set(3, &block).is_err());
+        drop(stack);
+        assert_eq!(std::fs::read(file.path()).unwrap(), before);
+
+        // Interrupt the journaled write before it reaches its block.
+        let mut raw = File::<512>::try_from(file.reopen().unwrap()).unwrap();
+        raw.set(5 * 8, &[0; 512]).unwrap();
+        drop(raw);
+
+        let result = Stack::open_read_only(&config);
+        assert!(matches!(result, Err(Error::Config(..))));
+
+        let mut stack = Stack::open(&config).unwrap();
+        stack.get(3, &mut block).unwrap();
+        assert_eq!(block[..], [0xaa; 4096][..]);
+    }
+
+    #[test]
+    fn partition() {
+        let file = image(128);
+        let raw = File::<512>::try_from(file.reopen().unwrap()).unwrap();
+        let mut disk = Disk::format(raw).unwrap();
+        let kind = quoin_gpt::Kind::LINUX_FILESYSTEM.guid;
+        disk.add(kind, 40..48, Attributes::empty(), "data").unwrap();
+
+        let config = Config {
+            layers: vec![
+                Layer::File {
+                    path: file.path().into(),
+                    block_size: 512,
+                },
+                Layer::Partition {
+                    number: None,
+                    label: Some("data".into()),
+                },
+            ],
+        };
+
+        let mut stack = Stack::open(&config).unwrap();
+        assert_eq!(stack.len(), 8);
+        stack.set(0, &[0x55; 512]).unwrap();
+
+        let mut raw = File::<512>::try_from(file.reopen().unwrap()).unwrap();
+        assert_eq!(raw.get(40).unwrap(), [0x55; 512]);
+        drop(stack);
+
+        // Damage the checksums of both table headers.
+        for index in [1, 127] {
+            let mut header = raw.get(index).unwrap();
+            header[16] ^= 1;
+            raw.set(index, &header).unwrap();
+        }
+
+        let err = Stack::open(&config).err().unwrap();
+        assert!(matches!(err, Error::Layer(..)));
+        assert_eq!(
+            err.to_string(),
+            "layer error: the partition table is corrupted"
+        );
+        assert!(std::error::Error::source(&err).is_some());
+    }
+
+    #[test]
+    fn invalid() {
+        let file = image(64);
+        let bottom = Layer::File {
+            path: file.path().into(),
+            block_size: 512,
+        };
+
+        let configs = vec![
+            vec![],
+            vec![bottom.clone(), bottom.clone()],
+            vec![bottom.clone(), Layer::Journal { block_size: 768 }],
+            vec![
+                bottom.clone(),
+                Layer::Hmac {
+                    hash: Hash::Sha256,
+                    key: Key::Hex("00".into()),
+                },
+            ],
+            vec![
+                bottom.clone(),
+                Layer::Hmac {
+                    hash: Hash::Sha384,
+                    key: Key::Hex(KEY[..96].into()),
+                },
+            ],
+            vec![
+                bottom,
+                Layer::Crypt {
+                    cipher: Cipher::Aes256Xts,
+                    key: Key::Env("QUOIN_STACK_TEST_MISSING".into()),
+                },
+            ],
+        ];
+
+        for layers in configs {
+            let result = Stack::open(&Config { layers });
+            assert!(matches!(result, Err(Error::Config(..))));
+        }
+
+        let missing = Layer::File {
+            path: "/nonexistent/quoin-stack-test".into(),
+            block_size: 512,
+        };
+        let err = Stack::open(&Config {
+            layers: vec![missing],
+        })
+        .err()
+        .unwrap();
+        assert!(matches!(err, Error::Io(..)));
+        assert!(std::error::Error::source(&err).is_some());
+    }
+}